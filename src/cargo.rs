@@ -0,0 +1,383 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    components::{self as cmp, ship, Cargo},
+    ships::{self, OrderError},
+};
+
+fn cell(world: &World, entity: Entity) -> Option<(u32, u32)> {
+    world.get::<cmp::Location>(entity).map(|loc| (loc.x, loc.y))
+}
+
+fn owner(world: &World, entity: Entity) -> Option<Entity> {
+    world.get::<cmp::Owner>(entity).map(|owner| owner.0)
+}
+
+/// Check `ship` is in the same star system as `planet`.
+fn check_docked(world: &World, ship: Entity, planet: Entity) -> Result<(), OrderError> {
+    let ship_cell = cell(world, ship).ok_or(OrderError::NoShip)?;
+    let planet_cell = cell(world, planet).ok_or(OrderError::NoDestination)?;
+    if ship_cell != planet_cell {
+        return Err(OrderError::NotDocked);
+    }
+    Ok(())
+}
+
+/// Check `ship` can take cargo orders, and return the player it takes them from.
+fn ship_owner(world: &World, ship: Entity) -> Result<Entity, OrderError> {
+    if world.get::<ship::Disabled>(ship).is_some() {
+        return Err(OrderError::Disabled);
+    }
+    owner(world, ship).ok_or(OrderError::NotOwned)
+}
+
+/// Move up to `amount` of `kind` from `planet` into the hold of `ship`, as much as the
+/// planet has and the hold fits. Returns how much was loaded.
+pub fn load(
+    world: &mut World,
+    ship: Entity,
+    planet: Entity,
+    kind: Cargo,
+    amount: u32,
+) -> Result<u32, OrderError> {
+    check_docked(world, ship, planet)?;
+    if owner(world, planet) != Some(ship_owner(world, ship)?) {
+        return Err(OrderError::NotOwned);
+    }
+    let free = world
+        .get::<ship::CargoBay>(ship)
+        .map(|bay| bay.max_cargo.saturating_sub(bay.current_cargo()))
+        .ok_or(OrderError::NoShip)?;
+    let Some(mut stockpile) = world.get_mut::<cmp::Stockpile>(planet) else {
+        return Ok(0);
+    };
+    let stock = stockpile.0.entry(kind).or_default();
+    let loaded = amount.min(*stock).min(free);
+    *stock -= loaded;
+    if loaded > 0 {
+        if let Some(mut bay) = world.get_mut::<ship::CargoBay>(ship) {
+            *bay.contents.entry(kind).or_default() += loaded;
+        }
+    }
+    Ok(loaded)
+}
+
+/// Empty every bit of `kind` in the hold of `ship` onto `planet`. Colonists may settle
+/// a planet nobody owns, which then belongs to the owner of the ship. Returns how much
+/// was unloaded.
+pub fn unload(
+    world: &mut World,
+    ship: Entity,
+    planet: Entity,
+    kind: Cargo,
+) -> Result<u32, OrderError> {
+    check_docked(world, ship, planet)?;
+    let ship_owner = ship_owner(world, ship)?;
+    let planet_owner = owner(world, planet);
+    let settles = kind == Cargo::Colonists && planet_owner.is_none();
+    if planet_owner != Some(ship_owner) && !settles {
+        return Err(OrderError::NotOwned);
+    }
+    let unloaded = world
+        .get_mut::<ship::CargoBay>(ship)
+        .ok_or(OrderError::NoShip)?
+        .contents
+        .remove(&kind)
+        .unwrap_or(0);
+    if unloaded == 0 {
+        return Ok(0);
+    }
+    let mut planet_entity = world.entity_mut(planet);
+    match planet_entity.get_mut::<cmp::Stockpile>() {
+        Some(mut stockpile) => *stockpile.0.entry(kind).or_default() += unloaded,
+        None => {
+            planet_entity.insert(cmp::Stockpile(BTreeMap::from([(kind, unloaded)])));
+        }
+    }
+    if settles {
+        log::info!("colonists from ship {:?} settled {:?}", ship, planet);
+        planet_entity.insert(cmp::Owner(ship_owner));
+    }
+    Ok(unloaded)
+}
+
+/// Put `ship` on a trade route between planets its owner holds.
+pub fn set_trade_route(
+    world: &mut World,
+    ship: Entity,
+    stops: Vec<ship::TradeStop>,
+) -> Result<(), OrderError> {
+    if stops.len() < 2 {
+        return Err(OrderError::TooFewStops);
+    }
+    if world.get::<ship::CargoBay>(ship).is_none() {
+        return Err(OrderError::NoShip);
+    }
+    let ship_owner = ship_owner(world, ship)?;
+    for stop in &stops {
+        if cell(world, stop.planet).is_none() {
+            return Err(OrderError::NoDestination);
+        }
+        if owner(world, stop.planet) != Some(ship_owner) {
+            return Err(OrderError::NotOwned);
+        }
+    }
+    log::info!("ship {:?} trading between {} stops", ship, stops.len());
+    world
+        .entity_mut(ship)
+        .insert(ship::TradeRoute { stops, next: 0 });
+    Ok(())
+}
+
+/// The star whose system `entity` is in: the star itself, or the star a planet or moon
+/// orbits.
+fn star_of(world: &World, entity: Entity) -> Option<Entity> {
+    let mut entity = entity;
+    while world.get::<cmp::astronomy::GalacticObj>(entity).is_none() {
+        entity = world.get::<Parent>(entity)?.get();
+    }
+    Some(entity)
+}
+
+/// Ships on a trade route that aren't flying anywhere work the stop they're at, then set
+/// off for the next one. A ship short of fuel waits where it is until it can make the trip.
+pub fn follow_trade_routes(world: &mut World) {
+    let idle: Vec<(Entity, ship::TradeRoute)> = world
+        .query_filtered::<(Entity, &ship::TradeRoute), Without<ship::Course>>()
+        .iter(world)
+        .map(|(entity, route)| (entity, route.clone()))
+        .collect();
+    for (ship, mut route) in idle {
+        let stop = route.stops[route.next % route.stops.len()].clone();
+        let Some(star) = star_of(world, stop.planet) else {
+            log::warn!(
+                "trade route stop {:?} of ship {:?} is gone",
+                stop.planet,
+                ship
+            );
+            world.entity_mut(ship).remove::<ship::TradeRoute>();
+            continue;
+        };
+        let mut destination = star;
+        if cell(world, ship) == cell(world, star) {
+            for &kind in &stop.unload {
+                if let Err(err) = unload(world, ship, stop.planet, kind) {
+                    log::warn!("ship {:?} can't unload {:?}: {}", ship, kind, err);
+                }
+            }
+            for &kind in &stop.load {
+                if let Err(err) = load(world, ship, stop.planet, kind, u32::MAX) {
+                    log::warn!("ship {:?} can't load {:?}: {}", ship, kind, err);
+                }
+            }
+            route.next = (route.next + 1) % route.stops.len();
+            let next_planet = route.stops[route.next].planet;
+            let Some(next_star) = star_of(world, next_planet) else {
+                log::warn!(
+                    "trade route stop {:?} of ship {:?} is gone",
+                    next_planet,
+                    ship
+                );
+                world.entity_mut(ship).remove::<ship::TradeRoute>();
+                continue;
+            };
+            destination = next_star;
+            world.entity_mut(ship).insert(route);
+        }
+        if let Err(err) = ships::set_course(world, ship, destination) {
+            log::debug!("ship {:?} waits on its trade route: {}", ship, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::test_utils::spawn_planet;
+
+    fn stock(world: &World, planet: Entity, kind: Cargo) -> u32 {
+        world
+            .get::<cmp::Stockpile>(planet)
+            .and_then(|stockpile| stockpile.0.get(&kind).copied())
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn test_load_and_unload() {
+        let mut world = World::new();
+        let player = world.spawn_empty().id();
+        let home = spawn_planet(&mut world, (0, 0), Some(player));
+        world
+            .entity_mut(home)
+            .insert(cmp::Stockpile(BTreeMap::from([
+                (Cargo::Resources, 80),
+                (Cargo::Probes, 2),
+            ])));
+        let ship = world.spawn((ships::scout((0, 0)), cmp::Owner(player))).id();
+
+        // the hold takes 50
+        assert_eq!(load(&mut world, ship, home, Cargo::Probes, 5), Ok(2));
+        assert_eq!(load(&mut world, ship, home, Cargo::Resources, 100), Ok(48));
+        assert_eq!(stock(&world, home, Cargo::Resources), 32);
+        let bay = world.get::<ship::CargoBay>(ship).unwrap();
+        assert_eq!(bay.current_cargo(), 50);
+
+        assert_eq!(unload(&mut world, ship, home, Cargo::Resources), Ok(48));
+        assert_eq!(stock(&world, home, Cargo::Resources), 80);
+
+        let elsewhere = spawn_planet(&mut world, (3, 0), Some(player));
+        assert_eq!(
+            load(&mut world, ship, elsewhere, Cargo::Resources, 1),
+            Err(OrderError::NotDocked)
+        );
+        let rival = world.spawn_empty().id();
+        let theirs = spawn_planet(&mut world, (0, 0), Some(rival));
+        assert_eq!(
+            unload(&mut world, ship, theirs, Cargo::Probes),
+            Err(OrderError::NotOwned)
+        );
+    }
+
+    #[test]
+    fn test_colonists_settle_unowned_planet() {
+        let mut world = World::new();
+        let player = world.spawn_empty().id();
+        let planet = spawn_planet(&mut world, (0, 0), None);
+        let mut bundle = ships::scout((0, 0));
+        bundle.cargo_bay.contents.insert(Cargo::Colonists, 10);
+        bundle.cargo_bay.contents.insert(Cargo::Resources, 10);
+        let ship = world.spawn((bundle, cmp::Owner(player))).id();
+
+        assert_eq!(
+            unload(&mut world, ship, planet, Cargo::Resources),
+            Err(OrderError::NotOwned)
+        );
+        assert_eq!(unload(&mut world, ship, planet, Cargo::Colonists), Ok(10));
+        assert_eq!(world.get::<cmp::Owner>(planet), Some(&cmp::Owner(player)));
+        assert_eq!(stock(&world, planet, Cargo::Colonists), 10);
+    }
+
+    #[test]
+    fn test_ownerless_and_disabled_ships_take_no_orders() {
+        let mut world = World::new();
+        let player = world.spawn_empty().id();
+        let unowned = spawn_planet(&mut world, (0, 0), None);
+        world
+            .entity_mut(unowned)
+            .insert(cmp::Stockpile(BTreeMap::from([(Cargo::Resources, 10)])));
+        let drifter = world.spawn(ships::scout((0, 0))).id();
+        assert_eq!(
+            load(&mut world, drifter, unowned, Cargo::Resources, 10),
+            Err(OrderError::NotOwned)
+        );
+
+        let home = spawn_planet(&mut world, (0, 0), Some(player));
+        let colony = spawn_planet(&mut world, (2, 0), Some(player));
+        let mut bundle = ships::scout((0, 0));
+        bundle.cargo_bay.contents.insert(Cargo::Colonists, 10);
+        let ship = world
+            .spawn((bundle, cmp::Owner(player), ship::Disabled))
+            .id();
+        assert_eq!(
+            load(&mut world, ship, home, Cargo::Resources, 10),
+            Err(OrderError::Disabled)
+        );
+        assert_eq!(
+            unload(&mut world, ship, unowned, Cargo::Colonists),
+            Err(OrderError::Disabled)
+        );
+        let stops = [home, colony]
+            .map(|planet| ship::TradeStop {
+                planet,
+                unload: vec![],
+                load: vec![],
+            })
+            .to_vec();
+        assert_eq!(
+            set_trade_route(&mut world, ship, stops),
+            Err(OrderError::Disabled)
+        );
+        assert_eq!(world.get::<cmp::Owner>(unowned), None);
+    }
+
+    #[test]
+    fn test_trade_route_shuttles_cargo() {
+        let mut app = App::new();
+        app.add_system(ships::move_ships)
+            .add_system(follow_trade_routes.after(ships::move_ships));
+        let world = &mut app.world;
+        let player = world.spawn_empty().id();
+        let mine = spawn_planet(world, (0, 0), Some(player));
+        world
+            .entity_mut(mine)
+            .insert(cmp::Stockpile(BTreeMap::from([(Cargo::Resources, 120)])));
+        let colony = spawn_planet(world, (2, 0), Some(player));
+        let ship = world.spawn((ships::scout((0, 0)), cmp::Owner(player))).id();
+        let stops = vec![
+            ship::TradeStop {
+                planet: mine,
+                unload: vec![],
+                load: vec![Cargo::Resources],
+            },
+            ship::TradeStop {
+                planet: colony,
+                unload: vec![Cargo::Resources],
+                load: vec![],
+            },
+        ];
+        assert_eq!(
+            set_trade_route(world, ship, stops[..1].to_vec()),
+            Err(OrderError::TooFewStops)
+        );
+        assert_eq!(set_trade_route(world, ship, stops), Ok(()));
+
+        // load and fly two cells, unload on arrival and fly two cells back: a round trip
+        // every 4 updates, so the second load arrives on the 7th
+        for _ in 0..7 {
+            app.update();
+        }
+        assert_eq!(stock(&app.world, colony, Cargo::Resources), 100);
+        assert_eq!(stock(&app.world, mine, Cargo::Resources), 20);
+        let route = app.world.get::<ship::TradeRoute>(ship).unwrap();
+        assert_eq!(route.stops.len(), 2);
+    }
+
+    #[test]
+    fn test_trade_route_ends_when_next_stop_is_gone() {
+        let mut app = App::new();
+        app.add_system(follow_trade_routes);
+        let world = &mut app.world;
+        let player = world.spawn_empty().id();
+        let mine = spawn_planet(world, (0, 0), Some(player));
+        world
+            .entity_mut(mine)
+            .insert(cmp::Stockpile(BTreeMap::from([(Cargo::Resources, 120)])));
+        let colony = spawn_planet(world, (2, 0), Some(player));
+        let ship = world.spawn((ships::scout((0, 0)), cmp::Owner(player))).id();
+        let stops = vec![
+            ship::TradeStop {
+                planet: mine,
+                unload: vec![],
+                load: vec![Cargo::Resources],
+            },
+            ship::TradeStop {
+                planet: colony,
+                unload: vec![Cargo::Resources],
+                load: vec![],
+            },
+        ];
+        assert_eq!(set_trade_route(world, ship, stops), Ok(()));
+        world.entity_mut(colony).despawn();
+
+        // the ship works the stop it is at, then gives up the route
+        app.update();
+        app.update();
+        assert_eq!(stock(&app.world, mine, Cargo::Resources), 70);
+        assert!(app.world.get::<ship::TradeRoute>(ship).is_none());
+    }
+}
@@ -0,0 +1,179 @@
+use std::{path::PathBuf, str::FromStr};
+
+use crate::{
+    clock::GameMode,
+    galaxy::GalaxyShape,
+    resources::{Config, ConfigError},
+};
+
+pub const USAGE: &str = "usage: stars [options]
+
+options:
+    --config <path>             read settings from this RON file (default: stars.ron)
+    --seed <n>                  seed for galaxy generation
+    --galaxy-dimension <n>      width and height of the galaxy grid
+    --num-stars <n>             number of stars to generate
+    --players <n>               number of players, each starting on their own homeworld
+    --shape <shape>             uniform, spiral, elliptical, ring, barred or clustered
+    --tick-rate <n>             simulation ticks per second at normal speed
+    --mode <mode>               realtime or turns
+    --help                      print this message";
+
+/// Command-line flags. Every setting is optional and, when given, overrides the
+/// value from the config file.
+#[derive(Debug, Default, PartialEq)]
+pub struct CliArgs {
+    pub help: bool,
+    pub config_path: Option<PathBuf>,
+    pub seed: Option<u64>,
+    pub galaxy_dimension: Option<u32>,
+    pub num_stars: Option<u32>,
+    pub num_players: Option<u32>,
+    pub shape: Option<GalaxyShape>,
+    pub tick_rate: Option<f64>,
+    pub mode: Option<GameMode>,
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, ConfigError> {
+    let value = value.ok_or_else(|| ConfigError::MissingValue(flag.to_string()))?;
+    value.parse().map_err(|_| ConfigError::InvalidValue {
+        flag: flag.to_string(),
+        value,
+    })
+}
+
+impl CliArgs {
+    /// Parse the arguments following the program name.
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<CliArgs, ConfigError> {
+        let mut cli_args = CliArgs::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => cli_args.help = true,
+                "--config" => cli_args.config_path = Some(parse_value(&arg, args.next())?),
+                "--seed" => cli_args.seed = Some(parse_value(&arg, args.next())?),
+                "--galaxy-dimension" => {
+                    cli_args.galaxy_dimension = Some(parse_value(&arg, args.next())?)
+                }
+                "--num-stars" => cli_args.num_stars = Some(parse_value(&arg, args.next())?),
+                "--players" => cli_args.num_players = Some(parse_value(&arg, args.next())?),
+                "--shape" => cli_args.shape = Some(parse_value(&arg, args.next())?),
+                "--tick-rate" => cli_args.tick_rate = Some(parse_value(&arg, args.next())?),
+                "--mode" => cli_args.mode = Some(parse_value(&arg, args.next())?),
+                _ => return Err(ConfigError::UnknownArgument(arg)),
+            }
+        }
+        Ok(cli_args)
+    }
+
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(seed) = self.seed {
+            config.seed = Some(seed);
+        }
+        if let Some(galaxy_dimension) = self.galaxy_dimension {
+            config.galaxy_dimension = galaxy_dimension;
+        }
+        if let Some(num_stars) = self.num_stars {
+            config.num_stars = num_stars;
+        }
+        if let Some(num_players) = self.num_players {
+            config.num_players = num_players;
+        }
+        if let Some(shape) = self.shape {
+            config.shape = shape;
+        }
+        if let Some(tick_rate) = self.tick_rate {
+            config.tick_rate = tick_rate;
+        }
+        if let Some(mode) = self.mode {
+            config.mode = mode;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::resources::ValidationError;
+
+    fn args(s: &str) -> impl Iterator<Item = String> {
+        s.split_whitespace()
+            .map(String::from)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let cli_args = CliArgs::parse(args(
+            "--seed 1234 --num-stars 10 --shape spiral --mode turns",
+        ))
+        .unwrap();
+        assert_eq!(
+            cli_args,
+            CliArgs {
+                seed: Some(1234),
+                num_stars: Some(10),
+                shape: Some(GalaxyShape::Spiral { arms: 4 }),
+                mode: Some(GameMode::TurnBased),
+                ..CliArgs::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        assert!(matches!(
+            CliArgs::parse(args("--seed")),
+            Err(ConfigError::MissingValue(_))
+        ));
+        assert!(matches!(
+            CliArgs::parse(args("--seed nope")),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            CliArgs::parse(args("--stars 10")),
+            Err(ConfigError::UnknownArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_overrides_are_validated() {
+        let cli_args = CliArgs::parse(args("--galaxy-dimension 5 --num-stars 25")).unwrap();
+        match Config::load(&cli_args) {
+            Err(ConfigError::Invalid(errors)) => assert_eq!(
+                errors,
+                vec![ValidationError::TooManyStars {
+                    num_stars: 25,
+                    max_stars: 24
+                }]
+            ),
+            other => panic!("expected validation error, got {:?}", other),
+        }
+        let cli_args = CliArgs::parse(args("--tick-rate 0")).unwrap();
+        assert!(matches!(
+            Config::load(&cli_args),
+            Err(ConfigError::Invalid(errors)) if errors == vec![ValidationError::InvalidTickRate(0.)]
+        ));
+        let cli_args = CliArgs::parse(args("--tick-rate 1e-20")).unwrap();
+        assert!(matches!(
+            Config::load(&cli_args),
+            Err(ConfigError::Invalid(errors)) if errors == vec![ValidationError::InvalidTickRate(1e-20)]
+        ));
+    }
+
+    #[test]
+    fn test_unknown_config_keys_are_errors() {
+        let path = std::env::temp_dir().join(format!("stars-test-{}.ron", std::process::id()));
+        std::fs::write(&path, "(num_star: 500)").unwrap();
+        let cli_args = CliArgs {
+            config_path: Some(path.clone()),
+            ..CliArgs::default()
+        };
+        let result = Config::load(&cli_args);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(ConfigError::Parse { .. })));
+    }
+}
@@ -0,0 +1,191 @@
+use std::{str::FromStr, time::Duration};
+
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::resources::{Config, EndTurn, Tick, TurnNumber};
+
+/// Whether the simulation runs continuously or one turn at a time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameMode {
+    #[default]
+    RealTime,
+    TurnBased,
+}
+
+impl FromStr for GameMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "realtime" | "real-time" => Ok(GameMode::RealTime),
+            "turns" | "turn-based" => Ok(GameMode::TurnBased),
+            _ => Err(format!("unknown game mode {:?}", s)),
+        }
+    }
+}
+
+/// Holds every system that advances the game: movement, production, combat. In real time
+/// it runs on every tick, in turn-based mode once per ended turn.
+#[derive(StageLabel)]
+pub struct SimulationStage;
+
+/// How long to wait for input while paused before redrawing anyway.
+pub const IDLE_POLL: Duration = Duration::from_millis(250);
+/// Most ticks run to catch up after a slow frame, so a stall doesn't snowball into an
+/// ever longer burst of updates.
+const MAX_CATCH_UP_TICKS: u32 = 5;
+
+/// Turns the real time between frames into a whole number of fixed-length ticks, carrying
+/// the remainder over to the next frame.
+#[derive(Default)]
+pub struct TickTimer {
+    lag: Duration,
+}
+
+impl TickTimer {
+    /// Add `elapsed` real time and return how many ticks are due.
+    pub fn advance(&mut self, elapsed: Duration, tick_length: Duration) -> u32 {
+        self.lag += elapsed;
+        let mut ticks = 0;
+        while self.lag >= tick_length {
+            self.lag -= tick_length;
+            ticks += 1;
+            if ticks == MAX_CATCH_UP_TICKS {
+                self.lag = Duration::ZERO;
+                break;
+            }
+        }
+        ticks
+    }
+
+    pub fn time_to_next_tick(&self, tick_length: Duration) -> Duration {
+        tick_length.saturating_sub(self.lag)
+    }
+}
+
+pub fn advance_tick(mut tick: ResMut<Tick>) {
+    tick.0 += 1;
+}
+
+pub fn simulation_should_run(config: Res<Config>, end_turn: Res<EndTurn>) -> ShouldRun {
+    match config.mode {
+        GameMode::RealTime => ShouldRun::Yes,
+        GameMode::TurnBased if end_turn.0 => ShouldRun::Yes,
+        GameMode::TurnBased => ShouldRun::No,
+    }
+}
+
+/// Close the turn the simulation just ran.
+pub fn finish_turn(mut end_turn: ResMut<EndTurn>, mut turn: ResMut<TurnNumber>) {
+    if end_turn.0 {
+        end_turn.0 = false;
+        turn.0 += 1;
+        log::info!("starting turn {}", turn.0);
+    }
+}
+
+/// Add the simulation stage and the resources it needs to `app`.
+pub fn add_simulation(app: &mut App) {
+    app.init_resource::<Tick>()
+        .init_resource::<TurnNumber>()
+        .init_resource::<EndTurn>()
+        .add_stage_after(
+            CoreStage::Update,
+            SimulationStage,
+            SystemStage::parallel().with_run_criteria(simulation_should_run),
+        )
+        .add_system(advance_tick)
+        .add_system_to_stage(CoreStage::Last, finish_turn);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_tick_timer_carries_remainder() {
+        let tick_length = Duration::from_millis(100);
+        let mut timer = TickTimer::default();
+        assert_eq!(timer.advance(Duration::from_millis(60), tick_length), 0);
+        assert_eq!(
+            timer.time_to_next_tick(tick_length),
+            Duration::from_millis(40)
+        );
+        assert_eq!(timer.advance(Duration::from_millis(60), tick_length), 1);
+        assert_eq!(timer.advance(Duration::from_millis(190), tick_length), 2);
+        assert_eq!(
+            timer.time_to_next_tick(tick_length),
+            Duration::from_millis(90)
+        );
+    }
+
+    #[test]
+    fn test_tick_timer_caps_catch_up() {
+        let tick_length = Duration::from_millis(100);
+        let mut timer = TickTimer::default();
+        assert_eq!(timer.advance(Duration::from_secs(10), tick_length), 5);
+        assert_eq!(timer.time_to_next_tick(tick_length), tick_length);
+    }
+
+    #[derive(Resource, Default)]
+    struct SimulationRuns(u32);
+
+    fn count_runs(mut runs: ResMut<SimulationRuns>) {
+        runs.0 += 1;
+    }
+
+    fn simulation_app(mode: GameMode) -> App {
+        let mut app = App::new();
+        app.insert_resource(Config {
+            mode,
+            ..Default::default()
+        })
+        .init_resource::<SimulationRuns>();
+        add_simulation(&mut app);
+        app.add_system_to_stage(SimulationStage, count_runs);
+        app
+    }
+
+    #[test]
+    fn test_real_time_simulates_every_update() {
+        let mut app = simulation_app(GameMode::RealTime);
+        for _ in 0..3 {
+            app.update();
+        }
+        assert_eq!(app.world.resource::<SimulationRuns>().0, 3);
+        assert_eq!(app.world.resource::<Tick>().0, 3);
+        assert_eq!(app.world.resource::<TurnNumber>().0, 0);
+    }
+
+    #[test]
+    fn test_turn_based_simulates_once_per_turn() {
+        let mut app = simulation_app(GameMode::TurnBased);
+        app.update();
+        app.update();
+        assert_eq!(app.world.resource::<SimulationRuns>().0, 0);
+
+        app.world.resource_mut::<EndTurn>().0 = true;
+        app.update();
+        app.update();
+        assert_eq!(app.world.resource::<SimulationRuns>().0, 1);
+        assert_eq!(app.world.resource::<TurnNumber>().0, 1);
+        assert!(!app.world.resource::<EndTurn>().0);
+    }
+
+    #[test]
+    fn test_game_speed_tick_length() {
+        use crate::resources::GameSpeed;
+
+        let mut speed = GameSpeed::default();
+        assert_eq!(speed.tick_length(4.), Some(Duration::from_millis(250)));
+        speed.multiplier = 5;
+        assert_eq!(speed.tick_length(4.), Some(Duration::from_millis(50)));
+        // too long to be a duration, e.g. from a bad config in an old save
+        assert_eq!(speed.tick_length(1e-20), None);
+        speed.paused = true;
+        assert_eq!(speed.tick_length(4.), None);
+    }
+}
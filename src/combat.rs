@@ -0,0 +1,321 @@
+use std::collections::{BTreeMap, HashSet};
+
+use bevy::prelude::*;
+
+use crate::{
+    components::{self as cmp, ship},
+    resources::{Tick, TurnNumber},
+};
+
+/// Most rounds a battle lasts before the survivors break off.
+pub const MAX_ROUNDS: u32 = 10;
+/// A ship whose hull falls to a quarter of its maximum or below is out of the fight.
+pub const DISABLED_HULL_FRACTION: u32 = 4;
+pub const SHIELD_RECHARGE_PER_TICK: u32 = 1;
+/// Older reports are dropped once there are this many.
+pub const MAX_REPORTS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Survived,
+    Disabled,
+    Destroyed,
+}
+
+/// A ship taking part in a battle.
+#[derive(Debug, Clone)]
+pub struct Combatant {
+    pub entity: Entity,
+    pub owner: Entity,
+    pub hull: ship::Hull,
+    pub shields: ship::Shields,
+    pub weapon: ship::DefenseSystem,
+    pub outcome: Outcome,
+}
+
+impl Combatant {
+    fn damage(&self) -> u32 {
+        match self.weapon {
+            ship::DefenseSystem::Laser(power) => power,
+        }
+    }
+
+    fn is_fighting(&self) -> bool {
+        self.outcome == Outcome::Survived
+    }
+
+    /// Shields soak up what they can, the hull takes the rest.
+    fn take_hit(&mut self, damage: u32) {
+        let absorbed = damage.min(self.shields.current);
+        self.shields.current -= absorbed;
+        self.hull.current = self.hull.current.saturating_sub(damage - absorbed);
+        if self.hull.current == 0 {
+            self.outcome = Outcome::Destroyed;
+        } else if self.hull.current * DISABLED_HULL_FRACTION <= self.hull.max {
+            self.outcome = Outcome::Disabled;
+        }
+    }
+}
+
+/// Fight until only one side has ships left in the fight or `MAX_ROUNDS` have passed.
+/// Each round every ship still fighting fires at the weakest enemy, all at once. Returns
+/// the number of rounds fought.
+pub fn resolve_battle(combatants: &mut [Combatant]) -> u32 {
+    let mut rounds = 0;
+    while rounds < MAX_ROUNDS {
+        let sides: HashSet<Entity> = combatants
+            .iter()
+            .filter(|combatant| combatant.is_fighting())
+            .map(|combatant| combatant.owner)
+            .collect();
+        if sides.len() < 2 {
+            break;
+        }
+        let hits: Vec<(usize, u32)> = combatants
+            .iter()
+            .filter(|attacker| attacker.is_fighting())
+            .filter_map(|attacker| {
+                let target = combatants
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, target)| target.is_fighting() && target.owner != attacker.owner)
+                    .min_by_key(|(idx, target)| {
+                        (target.hull.current + target.shields.current, *idx)
+                    })
+                    .map(|(idx, _)| idx)?;
+                Some((target, attacker.damage()))
+            })
+            .collect();
+        for (target, damage) in hits {
+            combatants[target].take_hit(damage);
+        }
+        rounds += 1;
+    }
+    rounds
+}
+
+/// How one ship fared in a battle.
+#[derive(Debug, Clone)]
+pub struct ShipReport {
+    pub name: String,
+    pub owner: Entity,
+    pub owner_name: String,
+    pub hull: ship::Hull,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Clone)]
+pub struct CombatReport {
+    pub cell: (u32, u32),
+    pub tick: u64,
+    pub turn: u64,
+    pub rounds: u32,
+    pub ships: Vec<ShipReport>,
+}
+
+/// Reports of recent battles, oldest first.
+#[derive(Resource, Debug, Default)]
+pub struct CombatLog {
+    pub reports: Vec<CombatReport>,
+}
+
+/// What a ship brings into a battle.
+type CombatShip<'a> = (
+    Entity,
+    &'a cmp::Owner,
+    &'a cmp::Location,
+    &'a mut ship::Hull,
+    &'a mut ship::Shields,
+    &'a ship::DefenseSystem,
+    Option<&'a cmp::Name>,
+);
+
+/// Ships of different players in the same cell fight it out. The destroyed are despawned,
+/// the disabled stop where they are.
+pub fn start_battles(
+    mut commands: Commands,
+    tick: Res<Tick>,
+    turn: Res<TurnNumber>,
+    mut combat_log: ResMut<CombatLog>,
+    mut ships: Query<CombatShip, Without<ship::Disabled>>,
+    players: Query<&cmp::Player>,
+) {
+    let mut cells: BTreeMap<(u32, u32), Vec<Combatant>> = BTreeMap::new();
+    for (entity, owner, loc, hull, shields, weapon, _) in &ships {
+        cells.entry((loc.x, loc.y)).or_default().push(Combatant {
+            entity,
+            owner: owner.0,
+            hull: *hull,
+            shields: *shields,
+            weapon: *weapon,
+            outcome: Outcome::Survived,
+        });
+    }
+    for (cell, mut combatants) in cells {
+        let sides: HashSet<Entity> = combatants.iter().map(|combatant| combatant.owner).collect();
+        if sides.len() < 2 {
+            continue;
+        }
+        combatants.sort_by_key(|combatant| combatant.entity);
+        let rounds = resolve_battle(&mut combatants);
+        log::info!("battle at {:?} over after {} rounds", cell, rounds);
+        let mut ship_reports = Vec::new();
+        for combatant in combatants {
+            let Ok((entity, _, _, mut hull, mut shields, _, name)) =
+                ships.get_mut(combatant.entity)
+            else {
+                continue;
+            };
+            *hull = combatant.hull;
+            *shields = combatant.shields;
+            match combatant.outcome {
+                Outcome::Destroyed => commands.entity(entity).despawn_recursive(),
+                Outcome::Disabled => {
+                    commands
+                        .entity(entity)
+                        .insert(ship::Disabled)
+                        .remove::<ship::Course>()
+                        .remove::<ship::TradeRoute>();
+                }
+                Outcome::Survived => {}
+            }
+            ship_reports.push(ShipReport {
+                name: name.map(|name| name.0.clone()).unwrap_or_default(),
+                owner: combatant.owner,
+                owner_name: players
+                    .get(combatant.owner)
+                    .map(|player| player.name.clone())
+                    .unwrap_or_default(),
+                hull: combatant.hull,
+                outcome: combatant.outcome,
+            });
+        }
+        combat_log.reports.push(CombatReport {
+            cell,
+            tick: tick.0,
+            turn: turn.0,
+            rounds,
+            ships: ship_reports,
+        });
+    }
+    let excess = combat_log.reports.len().saturating_sub(MAX_REPORTS);
+    combat_log.reports.drain(..excess);
+}
+
+pub fn recharge_shields(mut shields: Query<&mut ship::Shields>) {
+    for mut shields in &mut shields {
+        if shields.current < shields.max {
+            shields.current = (shields.current + SHIELD_RECHARGE_PER_TICK).min(shields.max);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::ships;
+
+    fn combatant(idx: u32, owner: Entity, hull: u32, shields: u32, laser: u32) -> Combatant {
+        Combatant {
+            entity: Entity::from_raw(idx),
+            owner,
+            hull: ship::Hull {
+                current: hull,
+                max: hull,
+            },
+            shields: ship::Shields {
+                current: shields,
+                max: shields,
+            },
+            weapon: ship::DefenseSystem::Laser(laser),
+            outcome: Outcome::Survived,
+        }
+    }
+
+    #[test]
+    fn test_shields_absorb_before_hull() {
+        let mut ship = combatant(0, Entity::from_raw(9), 20, 5, 1);
+        ship.take_hit(8);
+        assert_eq!((ship.shields.current, ship.hull.current), (0, 17));
+        ship.take_hit(12);
+        assert_eq!(ship.hull.current, 5);
+        assert_eq!(ship.outcome, Outcome::Disabled);
+        ship.take_hit(5);
+        assert_eq!(ship.outcome, Outcome::Destroyed);
+    }
+
+    #[test]
+    fn test_resolve_battle() {
+        let (red, blue) = (Entity::from_raw(100), Entity::from_raw(101));
+        // two weak red ships against a strong blue one
+        let mut combatants = vec![
+            combatant(0, red, 10, 0, 2),
+            combatant(1, red, 10, 0, 2),
+            combatant(2, blue, 40, 10, 10),
+        ];
+        let rounds = resolve_battle(&mut combatants);
+        assert_eq!(rounds, 2);
+        assert_eq!(combatants[0].outcome, Outcome::Destroyed);
+        assert_eq!(combatants[1].outcome, Outcome::Destroyed);
+        assert_eq!(combatants[2].outcome, Outcome::Survived);
+        assert_eq!(combatants[2].shields.current, 4);
+
+        // no enemies, no fight
+        let mut alone = vec![combatant(0, red, 10, 0, 2), combatant(1, red, 10, 0, 2)];
+        assert_eq!(resolve_battle(&mut alone), 0);
+    }
+
+    #[test]
+    fn test_battle_when_hostile_ships_meet() {
+        let mut app = App::new();
+        app.init_resource::<Tick>()
+            .init_resource::<TurnNumber>()
+            .init_resource::<CombatLog>()
+            .add_system(start_battles);
+        let world = &mut app.world;
+        let attacker = world
+            .spawn(cmp::Player {
+                name: "Player 1".to_string(),
+                color: cmp::PlayerColor::Blue,
+                homeworld: None,
+            })
+            .id();
+        let defender = world.spawn_empty().id();
+        let mut warship = ships::scout((2, 2));
+        warship.defense_system = ship::DefenseSystem::Laser(40);
+        let warship = world
+            .spawn((
+                warship,
+                cmp::Owner(attacker),
+                cmp::Name("Warship".to_string()),
+            ))
+            .id();
+        let victim = world
+            .spawn((ships::scout((2, 2)), cmp::Owner(defender)))
+            .id();
+        let bystander = world
+            .spawn((ships::scout((3, 2)), cmp::Owner(defender)))
+            .id();
+        app.update();
+
+        assert!(app.world.get_entity(victim).is_none());
+        assert!(app.world.get_entity(bystander).is_some());
+        let reports = &app.world.resource::<CombatLog>().reports;
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!((report.cell, report.rounds), ((2, 2), 1));
+        assert_eq!(report.ships[0].name, "Warship");
+        assert_eq!(report.ships[0].owner_name, "Player 1");
+        assert_eq!(report.ships[1].outcome, Outcome::Destroyed);
+        // the winner took a hit from the victim's laser on its shields
+        let shields = app.world.get::<ship::Shields>(warship).unwrap();
+        assert_eq!(shields.current, 7);
+
+        // nobody left to fight
+        app.update();
+        assert_eq!(app.world.resource::<CombatLog>().reports.len(), 1);
+    }
+}
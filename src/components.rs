@@ -1,6 +1,11 @@
+use std::collections::BTreeMap;
+
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-enum Structure {
+/// A building on a planet, spawned as a child of it.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Structure {
     Pipeline,
     ResearchLab,
     Factory,
@@ -9,35 +14,111 @@ enum Structure {
     SignalJammer,
 }
 
-#[derive(Component, Debug)]
+impl Structure {
+    pub const ALL: [Structure; 6] = [
+        Structure::Pipeline,
+        Structure::ResearchLab,
+        Structure::Factory,
+        Structure::SpacePort,
+        Structure::Radar,
+        Structure::SignalJammer,
+    ];
+}
+
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
 pub struct Location {
     pub x: u32,
     pub y: u32,
+    /// Orbital slot around the star at (x, y), counting from 1. 0 is the star itself.
     pub z: u32,
+    /// Orbital slot around the planet in slot z, counting from 1. 0 is the planet itself.
     pub w: u32,
     pub ui_offset: (f32, f32),
 }
 
-#[derive(Component)]
-struct Player {
-    name: String,
+#[cfg(test)]
+impl Location {
+    /// The star itself at cell (x, y).
+    pub fn at(x: u32, y: u32) -> Location {
+        Location {
+            x,
+            y,
+            z: 0,
+            w: 0,
+            ui_offset: (0., 0.),
+        }
+    }
+}
+
+/// A faction competing for the galaxy. Players are entities of their own, and everything
+/// they own points back at them through `Owner`.
+#[derive(Component, Debug, Clone)]
+pub struct Player {
+    pub name: String,
+    pub color: PlayerColor,
+    /// The planet the player starts from, if the galaxy had one to spare.
+    pub homeworld: Option<Entity>,
 }
 
-#[derive(Component)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlayerColor {
+    Blue,
+    Green,
+    White,
+    LightBlue,
+    LightGreen,
+    Gray,
+}
+
+impl PlayerColor {
+    /// Handed out to players in this order.
+    pub const ALL: [PlayerColor; 6] = [
+        PlayerColor::Blue,
+        PlayerColor::Green,
+        PlayerColor::White,
+        PlayerColor::LightBlue,
+        PlayerColor::LightGreen,
+        PlayerColor::Gray,
+    ];
+}
+
+/// The player a star, planet, ship or structure belongs to.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Owner(pub Entity);
+
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct Name(pub String);
 
-#[derive(Component)]
-struct Visibility {
+/// What ships carry and planets stockpile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Cargo {
+    Resources,
+    Colonists,
+    Probes,
+}
+
+impl Cargo {
+    pub const ALL: [Cargo; 3] = [Cargo::Resources, Cargo::Colonists, Cargo::Probes];
+}
+
+/// The goods kept on a planet, ready to be loaded onto ships.
+#[derive(Component, Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stockpile(pub BTreeMap<Cargo, u32>);
+
+/// Sensors: the owner of this entity sees every cell within `range` of it, give or take
+/// radars and jammers nearby (see `fog::effective_range`).
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Visibility {
     /*
-        Visibility can be jammed or certain ships could have stealth,
+        Certain ships could have stealth,
     */
-    range: u32,
+    pub range: u32,
 }
 
 pub mod astronomy {
     use super::*;
 
-    #[derive(Component)]
+    #[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
     pub enum GalacticObj {
         Star,
         SupermassiveBlackHole,
@@ -45,55 +126,158 @@ pub mod astronomy {
         Nebula,
     }
 
-    #[derive(Component)]
+    #[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
     pub enum StellarObj {
         Planet,
         Moon,
         AsteroidBelt,
     }
+
+    #[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum PlanetClass {
+        Barren,
+        Desert,
+        Rocky,
+        Ocean,
+        Ice,
+        GasGiant,
+    }
+
+    /// Physical attributes shared by planets, moons and asteroid belts.
+    #[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Body {
+        pub size: u32,
+        /// 0 (lethal) to 100 (earth-like).
+        pub habitability: u32,
+        /// 0 (none) to 100 (very rich).
+        pub minerals: u32,
+    }
 }
 
-mod structure {
+pub mod structure {
+    use std::collections::VecDeque;
+
     use super::*;
 
-    #[derive(Component)]
-    struct Size(u32);
+    /// How much room a structure takes up on its planet, out of the planet's `Body::size`.
+    #[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Size(pub u32);
+
+    /// A structure that has been paid for and waits to be finished.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Order {
+        pub structure: Structure,
+        /// Ticks of work left.
+        pub remaining: u32,
+    }
+
+    /// What a planet is building, one structure at a time in the order they were queued.
+    #[derive(Component, Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct ConstructionQueue(pub VecDeque<Order>);
+
+    /// Connects a pipeline to the planet it pumps resources to from the planet it's built
+    /// on, in another of its owner's star systems.
+    #[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PipelineLink {
+        pub to: Entity,
+        /// Most resources moved per tick.
+        pub capacity: u32,
+        /// Resources moved on the last tick.
+        pub flow: u32,
+        /// An end of the pipeline is in someone else's hands, so nothing flows.
+        pub disrupted: bool,
+    }
 }
 
-mod ship {
+pub mod ship {
+    use std::collections::VecDeque;
+
     use super::*;
 
-    #[derive(Component)] // TODO: does this need to be / should be a component?
-    enum FuelEfficiency {
+    #[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)] // TODO: does this need to be / should be a component?
+    pub enum FuelEfficiency {
         Fossil = 1,
         Solar = 2,
         Deuterium = 3,
     }
 
-    #[derive(Component)]
-    enum DefenseSystem {
+    /// The ship's weapon, with the damage it deals each round of combat.
+    #[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum DefenseSystem {
         Laser(u32),
     }
 
-    #[derive(Component)]
-    struct Engine {
-        current_fuel: u32,
-        max_fuel: u32,
-        fuel_efficiency: FuelEfficiency,
+    /// What's left of the ship's structure. At 0 the ship is destroyed.
+    #[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Hull {
+        pub current: u32,
+        pub max: u32,
     }
 
-    #[derive(Component)]
-    struct CargoBay {
-        current_cargo: u32,
-        max_cargo: u32,
+    /// Absorbs damage before the hull takes any, and recharges over time.
+    #[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Shields {
+        pub current: u32,
+        pub max: u32,
+    }
+
+    /// A ship beaten in combat but not destroyed: it can't fly or fight until it's repaired
+    /// at one of its owner's space ports.
+    #[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Disabled;
+
+    #[derive(Component, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Engine {
+        pub current_fuel: u32,
+        pub max_fuel: u32,
+        pub fuel_efficiency: FuelEfficiency,
+        /// Galaxy cells crossed per tick.
+        pub speed: u32,
+    }
+
+    #[derive(Component, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct CargoBay {
+        pub contents: BTreeMap<Cargo, u32>,
+        pub max_cargo: u32,
+    }
+
+    impl CargoBay {
+        pub fn current_cargo(&self) -> u32 {
+            self.contents.values().sum()
+        }
     }
 
     #[derive(Bundle)]
-    struct ShipBundle {
-        location: Location,
-        engine: Engine,
-        cargo_bay: CargoBay,
-        defense_system: DefenseSystem,
+    pub struct ShipBundle {
+        pub location: Location,
+        pub engine: Engine,
+        pub cargo_bay: CargoBay,
+        pub defense_system: DefenseSystem,
+        pub hull: Hull,
+        pub shields: Shields,
+    }
+
+    /// The star a ship is flying to and the cells it still has to cross, next one first.
+    #[derive(Component, Debug, Clone, PartialEq, Eq)]
+    pub struct Course {
+        pub destination: Entity,
+        pub path: VecDeque<(u32, u32)>,
+    }
+
+    /// A stop on a trade route: unload these kinds of cargo at `planet`, then load those.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct TradeStop {
+        pub planet: Entity,
+        pub unload: Vec<Cargo>,
+        pub load: Vec<Cargo>,
+    }
+
+    /// Stops a ship visits in turn, starting over after the last one.
+    #[derive(Component, Debug, Clone, PartialEq, Eq)]
+    pub struct TradeRoute {
+        pub stops: Vec<TradeStop>,
+        /// The stop the ship is heading to.
+        pub next: usize,
     }
 
     #[derive(Component)]
@@ -0,0 +1,285 @@
+use std::fmt;
+
+use bevy::prelude::*;
+
+use crate::components::{self as cmp, astronomy::Body, structure, Cargo, Structure};
+
+/// What it takes to build a structure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildSpec {
+    /// Resources taken from the planet's stockpile when the structure is queued.
+    pub cost: u32,
+    /// Ticks of work once it's at the front of the queue.
+    pub time: u32,
+    pub size: u32,
+}
+
+pub fn build_spec(structure: Structure) -> BuildSpec {
+    let (cost, time, size) = match structure {
+        Structure::Pipeline => (40, 3, 1),
+        Structure::ResearchLab => (120, 8, 2),
+        Structure::Factory => (150, 10, 2),
+        Structure::SpacePort => (200, 12, 2),
+        Structure::Radar => (80, 5, 1),
+        Structure::SignalJammer => (100, 6, 1),
+    };
+    BuildSpec { cost, time, size }
+}
+
+/// The components of a finished structure, to be spawned as a child of its planet.
+pub fn building(structure: Structure, owner: Entity) -> (Structure, structure::Size, cmp::Owner) {
+    (
+        structure,
+        structure::Size(build_spec(structure).size),
+        cmp::Owner(owner),
+    )
+}
+
+/// Why a structure can't be queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    NoPlanet,
+    NotOwned,
+    NoRoom { needed: u32, free: u32 },
+    TooFewResources { needed: u32, available: u32 },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::NoPlanet => write!(f, "nothing can be built there"),
+            BuildError::NotOwned => write!(f, "that planet belongs to someone else"),
+            BuildError::NoRoom { needed, free } => {
+                write!(f, "no room: needs {}, {} left", needed, free)
+            }
+            BuildError::TooFewResources { needed, available } => {
+                write!(f, "needs {} resources, has {}", needed, available)
+            }
+        }
+    }
+}
+
+/// Room left on `planet` for more structures, counting those still queued.
+pub fn free_room(world: &World, planet: Entity) -> u32 {
+    let Some(body) = world.get::<Body>(planet) else {
+        return 0;
+    };
+    let built: u32 = world
+        .get::<Children>(planet)
+        .map(|children| {
+            children
+                .iter()
+                .filter_map(|&child| world.get::<structure::Size>(child))
+                .map(|size| size.0)
+                .sum()
+        })
+        .unwrap_or(0);
+    let queued: u32 = world
+        .get::<structure::ConstructionQueue>(planet)
+        .map(|queue| {
+            queue
+                .0
+                .iter()
+                .map(|order| build_spec(order.structure).size)
+                .sum()
+        })
+        .unwrap_or(0);
+    body.size.saturating_sub(built + queued)
+}
+
+/// Pay for `structure` out of `planet`'s stockpile and put it at the back of the planet's
+/// construction queue.
+pub fn queue_structure(
+    world: &mut World,
+    player: Entity,
+    planet: Entity,
+    structure: Structure,
+) -> Result<(), BuildError> {
+    if world.get::<Body>(planet).is_none() {
+        return Err(BuildError::NoPlanet);
+    }
+    if world.get::<cmp::Owner>(planet) != Some(&cmp::Owner(player)) {
+        return Err(BuildError::NotOwned);
+    }
+    let spec = build_spec(structure);
+    let free = free_room(world, planet);
+    if spec.size > free {
+        return Err(BuildError::NoRoom {
+            needed: spec.size,
+            free,
+        });
+    }
+    let available = world
+        .get::<cmp::Stockpile>(planet)
+        .and_then(|stockpile| stockpile.0.get(&Cargo::Resources).copied())
+        .unwrap_or(0);
+    if spec.cost > available {
+        return Err(BuildError::TooFewResources {
+            needed: spec.cost,
+            available,
+        });
+    }
+    let mut planet_entity = world.entity_mut(planet);
+    if let Some(mut stockpile) = planet_entity.get_mut::<cmp::Stockpile>() {
+        if let Some(resources) = stockpile.0.get_mut(&Cargo::Resources) {
+            *resources -= spec.cost;
+        }
+    }
+    let order = structure::Order {
+        structure,
+        remaining: spec.time,
+    };
+    match planet_entity.get_mut::<structure::ConstructionQueue>() {
+        Some(mut queue) => queue.0.push_back(order),
+        None => {
+            planet_entity.insert(structure::ConstructionQueue([order].into()));
+        }
+    }
+    log::info!("{:?} queued on {:?}", structure, planet);
+    Ok(())
+}
+
+/// Work on the first structure in every owned planet's queue, and put it up once it's
+/// done. A planet without an owner builds nothing.
+pub fn build_structures(
+    mut commands: Commands,
+    mut planets: Query<(Entity, &cmp::Owner, &mut structure::ConstructionQueue)>,
+) {
+    for (planet, owner, mut queue) in &mut planets {
+        let Some(order) = queue.0.front_mut() else {
+            continue;
+        };
+        order.remaining = order.remaining.saturating_sub(1);
+        if order.remaining > 0 {
+            continue;
+        }
+        let structure = order.structure;
+        queue.0.pop_front();
+        log::info!("{:?} finished on {:?}", structure, planet);
+        commands.entity(planet).with_children(|planet| {
+            planet.spawn(building(structure, owner.0));
+        });
+    }
+}
+
+/// The structures standing on `planet`, counted by kind.
+pub fn structures_on(world: &World, planet: Entity) -> Vec<(Structure, u32)> {
+    let built: Vec<Structure> = world
+        .get::<Children>(planet)
+        .into_iter()
+        .flatten()
+        .filter_map(|&child| world.get::<Structure>(child).copied())
+        .collect();
+    Structure::ALL
+        .into_iter()
+        .map(|kind| {
+            (
+                kind,
+                built.iter().filter(|&&other| other == kind).count() as u32,
+            )
+        })
+        .filter(|&(_, count)| count > 0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_queue_and_build() {
+        let mut app = App::new();
+        app.add_system(build_structures);
+        let world = &mut app.world;
+        let player = world.spawn_empty().id();
+        let planet = world
+            .spawn((
+                Body {
+                    size: 3,
+                    habitability: 50,
+                    minerals: 50,
+                },
+                cmp::Owner(player),
+                cmp::Stockpile(BTreeMap::from([(Cargo::Resources, 300)])),
+            ))
+            .id();
+
+        assert_eq!(
+            queue_structure(world, player, planet, Structure::Factory),
+            Ok(())
+        );
+        assert_eq!(
+            queue_structure(world, player, planet, Structure::Radar),
+            Ok(())
+        );
+        // the factory and the radar fill the planet
+        assert_eq!(
+            queue_structure(world, player, planet, Structure::Pipeline),
+            Err(BuildError::NoRoom { needed: 1, free: 0 })
+        );
+        let resources = world.get::<cmp::Stockpile>(planet).unwrap().0[&Cargo::Resources];
+        assert_eq!(resources, 70);
+        let rival = world.spawn_empty().id();
+        assert_eq!(
+            queue_structure(world, rival, planet, Structure::Radar),
+            Err(BuildError::NotOwned)
+        );
+
+        for _ in 0..build_spec(Structure::Factory).time {
+            app.update();
+        }
+        assert_eq!(
+            structures_on(&app.world, planet),
+            vec![(Structure::Factory, 1)]
+        );
+        let queue = app
+            .world
+            .get::<structure::ConstructionQueue>(planet)
+            .unwrap();
+        assert_eq!(queue.0.len(), 1);
+        assert_eq!(queue.0[0].remaining, build_spec(Structure::Radar).time);
+
+        for _ in 0..build_spec(Structure::Radar).time {
+            app.update();
+        }
+        assert_eq!(structures_on(&app.world, planet).len(), 2);
+        assert!(app
+            .world
+            .get::<structure::ConstructionQueue>(planet)
+            .unwrap()
+            .0
+            .is_empty());
+    }
+
+    #[test]
+    fn test_too_few_resources() {
+        let mut world = World::new();
+        let player = world.spawn_empty().id();
+        let planet = world
+            .spawn((
+                Body {
+                    size: 10,
+                    habitability: 50,
+                    minerals: 50,
+                },
+                cmp::Owner(player),
+                cmp::Stockpile(BTreeMap::from([(Cargo::Resources, 50)])),
+            ))
+            .id();
+        assert_eq!(
+            queue_structure(&mut world, player, planet, Structure::SpacePort),
+            Err(BuildError::TooFewResources {
+                needed: 200,
+                available: 50
+            })
+        );
+        assert_eq!(
+            queue_structure(&mut world, player, player, Structure::Radar),
+            Err(BuildError::NoPlanet)
+        );
+    }
+}
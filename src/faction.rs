@@ -0,0 +1,203 @@
+use bevy::prelude::*;
+use rand::prelude::*;
+
+use crate::{
+    components::{self as cmp, astronomy::Body},
+    construction, ships,
+};
+
+/// How far a player sees from their home star at the start.
+pub const HOMEWORLD_SENSOR_RANGE: u32 = 4;
+/// What a homeworld has in store at the start.
+pub const STARTING_STOCKPILE: [(cmp::Cargo, u32); 3] = [
+    (cmp::Cargo::Resources, 200),
+    (cmp::Cargo::Colonists, 50),
+    (cmp::Cargo::Probes, 3),
+];
+
+/// A star system a player could start in.
+pub struct HomeworldCandidate {
+    pub star: Entity,
+    pub cell: (u32, u32),
+    pub planets: Vec<(Entity, Body)>,
+}
+
+/// Pick `count` of `cells` as far apart from each other as possible: a random first one,
+/// then each time the one furthest from all those already picked.
+pub fn spread_out<R: Rng>(cells: &[(u32, u32)], count: usize, rng: &mut R) -> Vec<usize> {
+    let mut picked: Vec<usize> = Vec::new();
+    if cells.is_empty() {
+        return picked;
+    }
+    picked.push(rng.gen_range(0..cells.len()));
+    let distance = |a: (u32, u32), b: (u32, u32)| {
+        (a.0 as i64 - b.0 as i64).pow(2) + (a.1 as i64 - b.1 as i64).pow(2)
+    };
+    while picked.len() < count.min(cells.len()) {
+        let furthest = (0..cells.len())
+            .filter(|idx| !picked.contains(idx))
+            .max_by_key(|&idx| {
+                picked
+                    .iter()
+                    .map(|&other| distance(cells[idx], cells[other]))
+                    .min()
+            });
+        match furthest {
+            Some(idx) => picked.push(idx),
+            None => break,
+        }
+    }
+    picked
+}
+
+/// Spawn `num_players` players and give each a homeworld: the most habitable planet of a
+/// star system far from the other players, owning the star along with it. The home star
+/// carries the player's first sensors, and a scout ship waits at the homeworld's space
+/// port.
+pub fn spawn_players<R: Rng>(
+    commands: &mut Commands,
+    candidates: &[HomeworldCandidate],
+    num_players: u32,
+    rng: &mut R,
+) -> Vec<Entity> {
+    let candidates: Vec<&HomeworldCandidate> = candidates
+        .iter()
+        .filter(|candidate| !candidate.planets.is_empty())
+        .collect();
+    let cells: Vec<(u32, u32)> = candidates.iter().map(|candidate| candidate.cell).collect();
+    let starts = spread_out(&cells, num_players as usize, rng);
+    if starts.len() < num_players as usize {
+        log::warn!(
+            "only {} star systems with planets for {} players",
+            starts.len(),
+            num_players
+        );
+    }
+
+    (0..num_players as usize)
+        .map(|idx| {
+            let start = starts.get(idx).map(|&start| candidates[start]);
+            let homeworld = start.and_then(|start| {
+                start
+                    .planets
+                    .iter()
+                    .max_by_key(|(_, body)| body.habitability)
+                    .map(|&(planet, _)| planet)
+            });
+            let player = commands
+                .spawn(cmp::Player {
+                    name: format!("Player {}", idx + 1),
+                    color: cmp::PlayerColor::ALL[idx % cmp::PlayerColor::ALL.len()],
+                    homeworld,
+                })
+                .id();
+            if let (Some(start), Some(homeworld)) = (start, homeworld) {
+                commands.entity(start.star).insert((
+                    cmp::Owner(player),
+                    cmp::Visibility {
+                        range: HOMEWORLD_SENSOR_RANGE,
+                    },
+                ));
+                commands
+                    .entity(homeworld)
+                    .insert((
+                        cmp::Owner(player),
+                        cmp::Stockpile(STARTING_STOCKPILE.into_iter().collect()),
+                    ))
+                    .with_children(|planet| {
+                        planet.spawn(construction::building(cmp::Structure::SpacePort, player));
+                    });
+                ships::spawn_ship(
+                    commands,
+                    ships::scout(start.cell),
+                    format!("Player {} Scout", idx + 1),
+                    player,
+                );
+                log::info!("player {} starts at {:?}", idx + 1, start.cell);
+            }
+            player
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bevy::ecs::system::CommandQueue;
+    use pretty_assertions::assert_eq;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn test_spread_out() {
+        let cells = [(0, 0), (1, 1), (9, 9), (5, 5), (0, 9)];
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let picked = spread_out(&cells, 3, &mut rng);
+        assert_eq!(picked.len(), 3);
+        // wherever it starts, the second pick is the cell furthest from the first
+        let furthest = (0..cells.len())
+            .max_by_key(|&idx| {
+                let (a, b) = (cells[idx], cells[picked[0]]);
+                (a.0 as i64 - b.0 as i64).pow(2) + (a.1 as i64 - b.1 as i64).pow(2)
+            })
+            .unwrap();
+        assert_eq!(picked[1], furthest);
+        // asking for more than there are gives every cell once
+        let mut picked = spread_out(&cells, 10, &mut rng);
+        picked.sort();
+        assert_eq!(picked, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_spawn_players() {
+        let mut world = World::new();
+        let body = |habitability| Body {
+            size: 3,
+            habitability,
+            minerals: 50,
+        };
+        let mut candidates = Vec::new();
+        for (cell, habitabilities) in [((0, 0), vec![10, 80]), ((9, 9), vec![40]), ((4, 4), vec![])]
+        {
+            let star = world.spawn_empty().id();
+            let planets = habitabilities
+                .into_iter()
+                .map(|habitability| (world.spawn(body(habitability)).id(), body(habitability)))
+                .collect();
+            candidates.push(HomeworldCandidate {
+                star,
+                cell,
+                planets,
+            });
+        }
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let players = spawn_players(&mut commands, &candidates, 3, &mut rng);
+        queue.apply(&mut world);
+
+        assert_eq!(players.len(), 3);
+        let mut habitabilities = Vec::new();
+        for &player in &players[..2] {
+            let homeworld = world.get::<cmp::Player>(player).unwrap().homeworld.unwrap();
+            assert_eq!(
+                world.get::<cmp::Owner>(homeworld),
+                Some(&cmp::Owner(player))
+            );
+            let port = world.get::<Children>(homeworld).unwrap()[0];
+            assert_eq!(
+                world.get::<cmp::Structure>(port),
+                Some(&cmp::Structure::SpacePort)
+            );
+            habitabilities.push(world.get::<Body>(homeworld).unwrap().habitability);
+        }
+        habitabilities.sort();
+        assert_eq!(habitabilities, vec![40, 80]);
+        // the system without planets can't be anyone's home
+        assert_eq!(
+            world.get::<cmp::Player>(players[2]).unwrap().homeworld,
+            None
+        );
+        assert_eq!(world.get::<cmp::Owner>(candidates[2].star), None);
+    }
+}
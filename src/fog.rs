@@ -0,0 +1,303 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+
+use crate::{components as cmp, resources};
+
+/// Sensor range a radar adds around its star.
+pub const RADAR_RANGE_BONUS: u32 = 3;
+/// Sensor range a signal jammer takes from every enemy sensor within `JAMMER_RADIUS`.
+pub const JAMMER_STRENGTH: u32 = 2;
+pub const JAMMER_RADIUS: u32 = 3;
+
+/// What a player knows about a galaxy cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellVisibility {
+    /// In sensor range right now.
+    Visible,
+    /// Seen before but not currently watched.
+    Explored,
+    Unexplored,
+}
+
+/// The cells one player can see now and every cell they have ever seen.
+#[derive(Debug, Clone, Default)]
+pub struct PlayerFog {
+    pub visible: HashSet<(u32, u32)>,
+    pub explored: HashSet<(u32, u32)>,
+}
+
+impl PlayerFog {
+    pub fn visibility(&self, cell: (u32, u32)) -> CellVisibility {
+        if self.visible.contains(&cell) {
+            CellVisibility::Visible
+        } else if self.explored.contains(&cell) {
+            CellVisibility::Explored
+        } else {
+            CellVisibility::Unexplored
+        }
+    }
+}
+
+/// Fog of war for every player, keyed by player entity.
+#[derive(Resource, Debug, Default)]
+pub struct FogOfWar {
+    pub players: HashMap<Entity, PlayerFog>,
+}
+
+fn within(a: (u32, u32), b: (u32, u32), range: u32) -> bool {
+    let (dx, dy) = (a.0 as i64 - b.0 as i64, a.1 as i64 - b.1 as i64);
+    dx.pow(2) + dy.pow(2) <= (range as i64).pow(2)
+}
+
+/// Every cell of the grid within `range` of `center`.
+pub fn cells_in_range(center: (u32, u32), range: u32, galaxy_dimension: u32) -> Vec<(u32, u32)> {
+    let (cx, cy) = (center.0 as i64, center.1 as i64);
+    let range = range as i64;
+    let in_grid = |v: i64| v >= 0 && v < galaxy_dimension as i64;
+    let mut cells = Vec::new();
+    for x in (cx - range)..=(cx + range) {
+        for y in (cy - range)..=(cy + range) {
+            if in_grid(x) && in_grid(y) && (x - cx).pow(2) + (y - cy).pow(2) <= range.pow(2) {
+                cells.push((x as u32, y as u32));
+            }
+        }
+    }
+    cells
+}
+
+/// A radar or signal jammer, placed at the star of the planet it stands on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SensorStructure {
+    pub structure: cmp::Structure,
+    pub owner: Entity,
+    pub cell: (u32, u32),
+}
+
+/// How far a sensor of `owner` at `cell` with a range of `base` sees: its owner's radars at
+/// the same star add to it, and every enemy jammer within `JAMMER_RADIUS` of it takes
+/// away from it.
+pub fn effective_range(
+    owner: Entity,
+    cell: (u32, u32),
+    base: u32,
+    structures: &[SensorStructure],
+) -> u32 {
+    let radars = structures
+        .iter()
+        .filter(|other| {
+            other.structure == cmp::Structure::Radar && other.owner == owner && other.cell == cell
+        })
+        .count() as u32;
+    let jammers = structures
+        .iter()
+        .filter(|other| {
+            other.structure == cmp::Structure::SignalJammer
+                && other.owner != owner
+                && within(other.cell, cell, JAMMER_RADIUS)
+        })
+        .count() as u32;
+    (base + radars * RADAR_RANGE_BONUS).saturating_sub(jammers * JAMMER_STRENGTH)
+}
+
+/// Recompute what each player sees from everything they own with sensors, and add it to
+/// what they have explored. A radar is a sensor of its own even where its owner has
+/// nothing else watching.
+pub fn update_fog_of_war(
+    config: Res<resources::Config>,
+    mut fog: ResMut<FogOfWar>,
+    players: Query<Entity, With<cmp::Player>>,
+    sensors: Query<(&cmp::Owner, &cmp::Location, &cmp::Visibility)>,
+    structures: Query<(&cmp::Structure, &cmp::Owner, &Parent)>,
+    locations: Query<&cmp::Location>,
+) {
+    for player in &players {
+        fog.players.entry(player).or_default().visible.clear();
+    }
+    let structures: Vec<SensorStructure> = structures
+        .iter()
+        .filter(|(structure, _, _)| {
+            matches!(
+                structure,
+                cmp::Structure::Radar | cmp::Structure::SignalJammer
+            )
+        })
+        .filter_map(|(&structure, owner, planet)| {
+            let loc = locations.get(planet.get()).ok()?;
+            Some(SensorStructure {
+                structure,
+                owner: owner.0,
+                cell: (loc.x, loc.y),
+            })
+        })
+        .collect();
+    let mut sources: Vec<(Entity, (u32, u32), u32)> = sensors
+        .iter()
+        .map(|(owner, loc, visibility)| (owner.0, (loc.x, loc.y), visibility.range))
+        .collect();
+    sources.extend(
+        structures
+            .iter()
+            .filter(|radar| radar.structure == cmp::Structure::Radar)
+            .map(|radar| (radar.owner, radar.cell, 0)),
+    );
+    for (owner, cell, base) in sources {
+        let Some(player_fog) = fog.players.get_mut(&owner) else {
+            continue;
+        };
+        let range = effective_range(owner, cell, base, &structures);
+        let cells = cells_in_range(cell, range, config.galaxy_dimension);
+        player_fog.visible.extend(cells);
+    }
+    for player_fog in fog.players.values_mut() {
+        let PlayerFog { visible, explored } = player_fog;
+        explored.extend(visible.iter().copied());
+    }
+}
+
+/// The fog the person at the keyboard plays under, or `None` when they can see everything.
+pub fn local_fog(world: &World) -> Option<&PlayerFog> {
+    if !world.get_resource::<resources::Config>()?.fog_of_war {
+        return None;
+    }
+    let player = world.get_resource::<resources::LocalPlayer>()?.0;
+    world.get_resource::<FogOfWar>()?.players.get(&player)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::test_utils;
+
+    #[test]
+    fn test_cells_in_range() {
+        assert_eq!(cells_in_range((5, 5), 0, 10), vec![(5, 5)]);
+        assert_eq!(cells_in_range((5, 5), 1, 10).len(), 5);
+        // a disc rather than a square: (2, 1) away is already out of range 2
+        assert_eq!(cells_in_range((5, 5), 2, 10).len(), 13);
+        // clipped at the edge of the grid
+        let mut cells = cells_in_range((0, 0), 1, 10);
+        cells.sort();
+        assert_eq!(cells, vec![(0, 0), (0, 1), (1, 0)]);
+    }
+
+    #[test]
+    fn test_effective_range() {
+        let (blue, red) = (Entity::from_raw(1), Entity::from_raw(2));
+        let structure = |structure, owner, cell| SensorStructure {
+            structure,
+            owner,
+            cell,
+        };
+        let radar = structure(cmp::Structure::Radar, blue, (5, 5));
+        assert_eq!(effective_range(blue, (5, 5), 4, &[]), 4);
+        assert_eq!(
+            effective_range(blue, (5, 5), 4, &[radar]),
+            4 + RADAR_RANGE_BONUS
+        );
+        // only the owner's sensors at the radar's own star gain from it
+        assert_eq!(effective_range(blue, (5, 6), 4, &[radar]), 4);
+        assert_eq!(effective_range(red, (5, 5), 4, &[radar]), 4);
+
+        let jammer = structure(cmp::Structure::SignalJammer, red, (5, 5 + JAMMER_RADIUS));
+        assert_eq!(
+            effective_range(blue, (5, 5), 4, &[radar, jammer]),
+            4 + RADAR_RANGE_BONUS - JAMMER_STRENGTH
+        );
+        // a jammer doesn't jam its owner, nor anyone out of its radius
+        assert_eq!(effective_range(red, (5, 5), 4, &[jammer]), 4);
+        assert_eq!(effective_range(blue, (9, 5), 4, &[jammer]), 4);
+        // and can't take the range below nothing
+        assert_eq!(effective_range(blue, (5, 5), 1, &[jammer, jammer]), 0);
+    }
+
+    #[test]
+    fn test_explored_outlives_visible() {
+        let mut app = App::new();
+        app.insert_resource(resources::Config {
+            galaxy_dimension: 10,
+            ..Default::default()
+        })
+        .init_resource::<FogOfWar>()
+        .add_system(update_fog_of_war);
+        let player = app
+            .world
+            .spawn(cmp::Player {
+                name: "Player 1".to_string(),
+                color: cmp::PlayerColor::Blue,
+                homeworld: None,
+            })
+            .id();
+        let scout = app
+            .world
+            .spawn((
+                cmp::Owner(player),
+                cmp::Location::at(2, 2),
+                cmp::Visibility { range: 1 },
+            ))
+            .id();
+        app.update();
+        let fog = &app.world.resource::<FogOfWar>().players[&player];
+        assert_eq!(fog.visibility((2, 3)), CellVisibility::Visible);
+        assert_eq!(fog.visibility((7, 7)), CellVisibility::Unexplored);
+
+        app.world.get_mut::<cmp::Location>(scout).unwrap().x = 7;
+        app.update();
+        let fog = &app.world.resource::<FogOfWar>().players[&player];
+        assert_eq!(fog.visibility((2, 3)), CellVisibility::Explored);
+        assert_eq!(fog.visibility((7, 2)), CellVisibility::Visible);
+    }
+
+    #[test]
+    fn test_radar_sees_without_other_sensors() {
+        let mut app = App::new();
+        app.insert_resource(resources::Config {
+            galaxy_dimension: 10,
+            ..Default::default()
+        })
+        .init_resource::<FogOfWar>()
+        .add_system(update_fog_of_war);
+        let player = |world: &mut World, name: &str| {
+            world
+                .spawn(cmp::Player {
+                    name: name.to_string(),
+                    color: cmp::PlayerColor::Blue,
+                    homeworld: None,
+                })
+                .id()
+        };
+        let blue = player(&mut app.world, "Blue");
+        let red = player(&mut app.world, "Red");
+        let colony = test_utils::spawn_planet(&mut app.world, (2, 2), None);
+        app.world.entity_mut(colony).with_children(|planet| {
+            planet.spawn((cmp::Structure::Radar, cmp::Owner(blue)));
+        });
+        app.update();
+        let fog = &app.world.resource::<FogOfWar>().players[&blue];
+        assert_eq!(
+            fog.visibility((2, 2 + RADAR_RANGE_BONUS)),
+            CellVisibility::Visible
+        );
+        assert_eq!(
+            fog.visibility((2, 3 + RADAR_RANGE_BONUS)),
+            CellVisibility::Unexplored
+        );
+
+        // a red jammer next door cuts the radar down
+        let outpost = test_utils::spawn_planet(&mut app.world, (4, 2), None);
+        app.world.entity_mut(outpost).with_children(|planet| {
+            planet.spawn((cmp::Structure::SignalJammer, cmp::Owner(red)));
+        });
+        app.update();
+        let fog = &app.world.resource::<FogOfWar>().players[&blue];
+        let jammed_range = RADAR_RANGE_BONUS - JAMMER_STRENGTH;
+        assert_eq!(
+            fog.visibility((2, 2 + jammed_range)),
+            CellVisibility::Visible
+        );
+        assert!(!fog.visible.contains(&(2, 3 + jammed_range)));
+    }
+}
@@ -0,0 +1,356 @@
+use std::{collections::HashSet, f64::consts::TAU, str::FromStr};
+
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// The overall layout stars are scattered in, selected through `Config::shape`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum GalaxyShape {
+    #[default]
+    Uniform,
+    Spiral {
+        arms: u32,
+    },
+    Elliptical,
+    Ring,
+    Barred,
+    Clustered {
+        clusters: u32,
+    },
+}
+
+impl FromStr for GalaxyShape {
+    type Err = String;
+
+    /// Parse a shape by name, using default parameters for the shapes that take any.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "uniform" => Ok(GalaxyShape::Uniform),
+            "spiral" => Ok(GalaxyShape::Spiral { arms: 4 }),
+            "elliptical" => Ok(GalaxyShape::Elliptical),
+            "ring" => Ok(GalaxyShape::Ring),
+            "barred" => Ok(GalaxyShape::Barred),
+            "clustered" => Ok(GalaxyShape::Clustered { clusters: 5 }),
+            _ => Err(format!("unknown galaxy shape {:?}", s)),
+        }
+    }
+}
+
+/// Produces candidate star positions in normalized galaxy space, where the galactic core
+/// is at (0, 0) and the edges of the grid are at -1 and 1. Points outside that square are
+/// discarded by the caller, so generators don't need to clamp.
+pub trait ShapeGenerator {
+    fn sample(&self, rng: &mut dyn RngCore) -> (f64, f64);
+}
+
+impl GalaxyShape {
+    pub fn generator(&self, rng: &mut dyn RngCore) -> Box<dyn ShapeGenerator> {
+        match *self {
+            GalaxyShape::Uniform => Box::new(Uniform),
+            GalaxyShape::Spiral { arms } => Box::new(Spiral {
+                arms: arms.max(1),
+                winding: 1.25,
+            }),
+            GalaxyShape::Elliptical => Box::new(Elliptical { flattening: 0.6 }),
+            GalaxyShape::Ring => Box::new(Ring {
+                radius: 0.7,
+                width: 0.08,
+            }),
+            GalaxyShape::Barred => Box::new(Barred {
+                bar_length: 0.45,
+                arms: Spiral {
+                    arms: 2,
+                    winding: 0.75,
+                },
+            }),
+            GalaxyShape::Clustered { clusters } => Box::new(Clustered {
+                centers: (0..clusters.max(1))
+                    .map(|_| (rng.gen_range(-0.8..0.8), rng.gen_range(-0.8..0.8)))
+                    .collect(),
+                spread: 0.12,
+            }),
+        }
+    }
+}
+
+/// Standard normal sample via the Box-Muller transform.
+fn gaussian(rng: &mut dyn RngCore) -> f64 {
+    let u1: f64 = 1. - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    (-2. * u1.ln()).sqrt() * (TAU * u2).cos()
+}
+
+fn polar(radius: f64, angle: f64) -> (f64, f64) {
+    (radius * angle.cos(), radius * angle.sin())
+}
+
+pub struct Uniform;
+
+impl ShapeGenerator for Uniform {
+    fn sample(&self, rng: &mut dyn RngCore) -> (f64, f64) {
+        (rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0))
+    }
+}
+
+pub struct Spiral {
+    pub arms: u32,
+    /// Number of full turns an arm makes between the core and the rim.
+    pub winding: f64,
+}
+
+impl ShapeGenerator for Spiral {
+    fn sample(&self, rng: &mut dyn RngCore) -> (f64, f64) {
+        // a fifth of the stars make up the central bulge
+        if rng.gen_bool(0.2) {
+            return (gaussian(rng) * 0.15, gaussian(rng) * 0.15);
+        }
+        let arm = rng.gen_range(0..self.arms) as f64;
+        let t: f64 = rng.gen_range(0.1..1.0);
+        let angle = arm * TAU / self.arms as f64 + t * self.winding * TAU;
+        let (x, y) = polar(t * 0.95, angle);
+        let scatter = 0.04 + 0.06 * t;
+        (x + gaussian(rng) * scatter, y + gaussian(rng) * scatter)
+    }
+}
+
+pub struct Elliptical {
+    /// Ratio of the minor to the major axis.
+    pub flattening: f64,
+}
+
+impl ShapeGenerator for Elliptical {
+    fn sample(&self, rng: &mut dyn RngCore) -> (f64, f64) {
+        let radius = (gaussian(rng) * 0.4).abs();
+        let (x, y) = polar(radius, rng.gen_range(0.0..TAU));
+        (x, y * self.flattening)
+    }
+}
+
+pub struct Ring {
+    pub radius: f64,
+    pub width: f64,
+}
+
+impl ShapeGenerator for Ring {
+    fn sample(&self, rng: &mut dyn RngCore) -> (f64, f64) {
+        let radius = self.radius + gaussian(rng) * self.width;
+        polar(radius, rng.gen_range(0.0..TAU))
+    }
+}
+
+pub struct Barred {
+    /// Half the length of the bar through the core.
+    pub bar_length: f64,
+    pub arms: Spiral,
+}
+
+impl ShapeGenerator for Barred {
+    fn sample(&self, rng: &mut dyn RngCore) -> (f64, f64) {
+        if rng.gen_bool(0.35) {
+            return (
+                rng.gen_range(-self.bar_length..self.bar_length),
+                gaussian(rng) * 0.06,
+            );
+        }
+        // arms start at the ends of the bar and wind outwards from there
+        let arm = rng.gen_range(0..self.arms.arms) as f64;
+        let t: f64 = rng.gen();
+        let angle = arm * TAU / self.arms.arms as f64 + t * self.arms.winding * TAU;
+        let (x, y) = polar(self.bar_length + t * (0.95 - self.bar_length), angle);
+        (x + gaussian(rng) * 0.05, y + gaussian(rng) * 0.05)
+    }
+}
+
+pub struct Clustered {
+    pub centers: Vec<(f64, f64)>,
+    pub spread: f64,
+}
+
+impl ShapeGenerator for Clustered {
+    fn sample(&self, rng: &mut dyn RngCore) -> (f64, f64) {
+        let (cx, cy) = self.centers[rng.gen_range(0..self.centers.len())];
+        (
+            cx + gaussian(rng) * self.spread,
+            cy + gaussian(rng) * self.spread,
+        )
+    }
+}
+
+/// Map a normalized point onto the `dimension` x `dimension` grid, if it falls inside it.
+pub fn to_grid(point: (f64, f64), dimension: u32) -> Option<(u32, u32)> {
+    let scale = |p: f64| ((p + 1.) / 2. * dimension as f64).floor();
+    let (x, y) = (scale(point.0), scale(point.1));
+    let bounds = 0. ..dimension as f64;
+    if bounds.contains(&x) && bounds.contains(&y) {
+        Some((x as u32, y as u32))
+    } else {
+        None
+    }
+}
+
+/// The cell at the center of the grid, where the supermassive black hole sits.
+pub fn galactic_core(dimension: u32) -> (u32, u32) {
+    (dimension / 2, dimension / 2)
+}
+
+fn free_cells(dimension: u32, occupied: &HashSet<(u32, u32)>) -> Vec<(u32, u32)> {
+    (0..dimension)
+        .flat_map(|x| (0..dimension).map(move |y| (x, y)))
+        .filter(|cell| !occupied.contains(cell))
+        .collect()
+}
+
+/// Pick `num_stars` distinct, unoccupied grid cells following `shape` and mark them as
+/// occupied. Dense shapes can run out of free cells before every star is placed, so after
+/// enough misses the remaining stars are spread over whatever cells are still free.
+pub fn place_stars<R: Rng>(
+    shape: &GalaxyShape,
+    num_stars: u32,
+    dimension: u32,
+    occupied: &mut HashSet<(u32, u32)>,
+    rng: &mut R,
+) -> Vec<(u32, u32)> {
+    let generator = shape.generator(rng);
+    let max_attempts = num_stars as usize * 50;
+    let mut positions = Vec::with_capacity(num_stars as usize);
+    let mut attempts = 0;
+    while positions.len() < num_stars as usize && attempts < max_attempts {
+        attempts += 1;
+        let Some(cell) = to_grid(generator.sample(rng), dimension) else {
+            continue;
+        };
+        if occupied.insert(cell) {
+            positions.push(cell);
+        }
+    }
+
+    let missing = num_stars as usize - positions.len();
+    if missing > 0 {
+        log::debug!(
+            "{:?} only placed {} stars, scattering {} more",
+            shape,
+            positions.len(),
+            missing
+        );
+        positions.extend(scatter(missing as u32, dimension, occupied, rng));
+    }
+    positions
+}
+
+/// Pick up to `count` free cells uniformly at random and mark them as occupied.
+pub fn scatter<R: Rng>(
+    count: u32,
+    dimension: u32,
+    occupied: &mut HashSet<(u32, u32)>,
+    rng: &mut R,
+) -> Vec<(u32, u32)> {
+    let mut cells = free_cells(dimension, occupied);
+    cells.shuffle(rng);
+    cells.truncate(count as usize);
+    occupied.extend(cells.iter().copied());
+    cells
+}
+
+/// Grow a nebula of up to `size` connected free cells from a random free starting cell,
+/// marking them as occupied. Returns fewer cells if the nebula gets boxed in.
+pub fn grow_nebula<R: Rng>(
+    size: u32,
+    dimension: u32,
+    occupied: &mut HashSet<(u32, u32)>,
+    rng: &mut R,
+) -> Vec<(u32, u32)> {
+    let mut cells = scatter(1, dimension, occupied, rng);
+    while !cells.is_empty() && cells.len() < size as usize {
+        let mut frontier: Vec<(u32, u32)> = cells
+            .iter()
+            .flat_map(|&(x, y)| {
+                [
+                    (x.wrapping_sub(1), y),
+                    (x + 1, y),
+                    (x, y.wrapping_sub(1)),
+                    (x, y + 1),
+                ]
+            })
+            .filter(|&(x, y)| x < dimension && y < dimension && !occupied.contains(&(x, y)))
+            .collect();
+        frontier.sort();
+        frontier.dedup();
+        let Some(&cell) = frontier.choose(rng) else {
+            break;
+        };
+        occupied.insert(cell);
+        cells.push(cell);
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use rand_chacha::ChaCha8Rng;
+
+    const SHAPES: [GalaxyShape; 6] = [
+        GalaxyShape::Uniform,
+        GalaxyShape::Spiral { arms: 3 },
+        GalaxyShape::Elliptical,
+        GalaxyShape::Ring,
+        GalaxyShape::Barred,
+        GalaxyShape::Clustered { clusters: 4 },
+    ];
+
+    #[test]
+    fn test_place_stars_unique_and_in_bounds() {
+        for shape in SHAPES {
+            let mut rng = ChaCha8Rng::seed_from_u64(7);
+            let positions = place_stars(&shape, 80, 25, &mut HashSet::new(), &mut rng);
+            let unique: HashSet<_> = positions.iter().collect();
+            assert_eq!(positions.len(), 80, "{:?}", shape);
+            assert_eq!(unique.len(), 80, "{:?}", shape);
+            assert!(positions.iter().all(|&(x, y)| x < 25 && y < 25));
+        }
+    }
+
+    #[test]
+    fn test_place_stars_fills_grid() {
+        for shape in SHAPES {
+            let mut rng = ChaCha8Rng::seed_from_u64(7);
+            let positions = place_stars(&shape, 100, 10, &mut HashSet::new(), &mut rng);
+            let unique: HashSet<_> = positions.iter().collect();
+            assert_eq!(unique.len(), 100, "{:?}", shape);
+        }
+    }
+
+    #[test]
+    fn test_place_stars_skips_occupied() {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let core = galactic_core(10);
+        let mut occupied = HashSet::from([core]);
+        let positions = place_stars(&GalaxyShape::Elliptical, 99, 10, &mut occupied, &mut rng);
+        assert!(!positions.contains(&core));
+        assert_eq!(occupied.len(), 100);
+    }
+
+    #[test]
+    fn test_grow_nebula_is_connected() {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let mut occupied = HashSet::new();
+        let cells = grow_nebula(6, 25, &mut occupied, &mut rng);
+        assert_eq!(cells.len(), 6);
+        for (i, &(x, y)) in cells.iter().enumerate().skip(1) {
+            assert!(cells[..i]
+                .iter()
+                .any(|&(px, py)| px.abs_diff(x) + py.abs_diff(y) == 1));
+        }
+    }
+
+    #[test]
+    fn test_to_grid() {
+        assert_eq!(to_grid((-1., -1.), 10), Some((0, 0)));
+        assert_eq!(to_grid((0., 0.), 10), Some((5, 5)));
+        assert_eq!(to_grid((0.99, 0.), 10), Some((9, 5)));
+        assert_eq!(to_grid((1., 0.), 10), None);
+        assert_eq!(to_grid((-1.01, 0.), 10), None);
+    }
+}
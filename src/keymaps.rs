@@ -1,7 +1,185 @@
 use bevy::prelude::App;
 use crossterm::event;
+use tui_textarea::{Input, Key};
 
-use crate::ui;
+use crate::{cargo, clock, components as cmp, construction, pipelines, resources, save, ships, ui};
+
+/// tui-textarea reads events from an older crossterm than ours, so translate by hand.
+fn textarea_input(key: event::KeyEvent) -> Input {
+    let key_code = match key.code {
+        event::KeyCode::Char(c) => Key::Char(c),
+        event::KeyCode::Backspace => Key::Backspace,
+        event::KeyCode::Delete => Key::Delete,
+        event::KeyCode::Left => Key::Left,
+        event::KeyCode::Right => Key::Right,
+        event::KeyCode::Home => Key::Home,
+        event::KeyCode::End => Key::End,
+        _ => Key::Null,
+    };
+    Input {
+        key: key_code,
+        ctrl: key.modifiers.contains(event::KeyModifiers::CONTROL),
+        alt: key.modifiers.contains(event::KeyModifiers::ALT),
+    }
+}
+
+/// While the search modal is open every key goes to it, so typing a name can't
+/// trigger any other binding.
+fn handle_search_key(key: event::KeyEvent, tui_state: &mut ui::TuiState, app: &mut App) {
+    match key.code {
+        event::KeyCode::Esc => tui_state.active_modal = ui::Modal::Off,
+        event::KeyCode::Down | event::KeyCode::Tab => tui_state.search.select_next(),
+        event::KeyCode::Up | event::KeyCode::BackTab => tui_state.search.select_prev(),
+        event::KeyCode::Enter => {
+            let Some(result) = tui_state.search.selected().cloned() else {
+                return;
+            };
+            log::info!("search jumped to {} at {:?}", result.name, result.cell);
+            tui_state.active_modal = ui::Modal::Off;
+            tui_state.active_view = ui::View::Galaxy;
+            tui_state.system_view.star = None;
+            let galaxy_view = &mut tui_state.galaxy_view;
+            // a ship may be out in open space, with nothing else in its cell to select
+            if app.world.get::<cmp::ship::Engine>(result.entity).is_some() {
+                galaxy_view.selected_ship = Some(result.entity);
+                galaxy_view.order_message = None;
+            }
+            galaxy_view.select_cell(result.cell);
+            let galaxy_dimension = app.world.resource::<resources::Config>().galaxy_dimension;
+            galaxy_view.center_on_cell(result.cell, galaxy_dimension);
+        }
+        _ => {
+            if tui_state.search.textarea.input(textarea_input(key)) {
+                tui_state.search.update_results(app);
+            }
+        }
+    }
+}
+
+/// Orders for moving cargo between the selected ship and the selected body in the
+/// system view.
+fn handle_cargo_order(key_code: event::KeyCode, tui_state: &mut ui::TuiState, app: &mut App) {
+    let system_view = &mut tui_state.system_view;
+    if key_code == event::KeyCode::Char('c') {
+        system_view.next_cargo_kind();
+        return;
+    }
+    let Some(ship) = tui_state.galaxy_view.selected_ship else {
+        tui_state.galaxy_view.order_message = Some("Select a ship with 's' first".to_string());
+        return;
+    };
+    let body = system_view.star.and_then(|star| {
+        ui::query_system_bodies(app, star)
+            .get(system_view.selected_idx)
+            .map(|body| body.entity)
+    });
+    let kind = system_view.cargo_kind;
+    let result = match (key_code, body) {
+        (event::KeyCode::Char('T'), _) => {
+            system_view.route_stops.clear();
+            match app.world.get_entity_mut(ship) {
+                Some(mut ship) => {
+                    ship.remove::<cmp::ship::TradeRoute>();
+                    Ok("Trade route cleared".to_string())
+                }
+                None => Err(ships::OrderError::NoShip),
+            }
+        }
+        (_, None) => return,
+        (event::KeyCode::Char('l'), Some(body)) => {
+            cargo::load(&mut app.world, ship, body, kind, u32::MAX)
+                .map(|loaded| format!("Loaded {} {}", loaded, ui::cargo_label(kind)))
+        }
+        (event::KeyCode::Char('u'), Some(body)) => cargo::unload(&mut app.world, ship, body, kind)
+            .map(|unloaded| format!("Unloaded {} {}", unloaded, ui::cargo_label(kind))),
+        (_, Some(body)) => {
+            system_view.route_stops.push(body);
+            if system_view.route_stops.len() < 2 {
+                Ok("Pick the next stop of the route".to_string())
+            } else {
+                cargo::set_trade_route(&mut app.world, ship, system_view.trade_stops())
+                    .map(|()| format!("Trade route of {} stops", system_view.route_stops.len()))
+            }
+        }
+    };
+    tui_state.galaxy_view.order_message = Some(match result {
+        Ok(message) => message,
+        Err(err) => format!("Order refused: {}", err),
+    });
+}
+
+/// Queue the chosen structure on the body selected in the system view.
+fn handle_build_order(tui_state: &mut ui::TuiState, app: &mut App) {
+    let system_view = &tui_state.system_view;
+    let Some(body) = system_view.star.and_then(|star| {
+        ui::query_system_bodies(app, star)
+            .get(system_view.selected_idx)
+            .map(|body| body.entity)
+    }) else {
+        return;
+    };
+    let Some(player) = app
+        .world
+        .get_resource::<resources::LocalPlayer>()
+        .map(|player| player.0)
+    else {
+        return;
+    };
+    let structure = system_view.structure_kind;
+    tui_state.galaxy_view.order_message = Some(
+        match construction::queue_structure(&mut app.world, player, body, structure) {
+            Ok(()) => format!("Queued a {}", ui::structure_label(structure)),
+            Err(err) => format!("Can't build: {}", err),
+        },
+    );
+}
+
+/// The first press picks the selected body to pipe resources from, the second connects
+/// it to the body selected then, usually in another star system.
+fn handle_pipeline_order(tui_state: &mut ui::TuiState, app: &mut App) {
+    let system_view = &mut tui_state.system_view;
+    let Some(body) = system_view.star.and_then(|star| {
+        ui::query_system_bodies(app, star)
+            .get(system_view.selected_idx)
+            .map(|body| body.entity)
+    }) else {
+        return;
+    };
+    let Some(from) = system_view.pipeline_from.take() else {
+        system_view.pipeline_from = Some(body);
+        tui_state.galaxy_view.order_message =
+            Some("Pick the planet to pipe resources to".to_string());
+        return;
+    };
+    let Some(player) = app
+        .world
+        .get_resource::<resources::LocalPlayer>()
+        .map(|player| player.0)
+    else {
+        return;
+    };
+    tui_state.galaxy_view.order_message = Some(
+        match pipelines::connect(&mut app.world, player, from, body) {
+            Ok(()) => "Pipeline connected".to_string(),
+            Err(err) => format!("Can't connect: {}", err),
+        },
+    );
+}
+
+/// While a combat report is open the arrows page through the others.
+fn handle_report_key(key: event::KeyEvent, tui_state: &mut ui::TuiState, app: &mut App) {
+    let num_reports = ui::query_combat_reports(app).len();
+    match key.code {
+        event::KeyCode::Esc | event::KeyCode::Char('r') => tui_state.active_modal = ui::Modal::Off,
+        event::KeyCode::Left | event::KeyCode::Down if tui_state.report_idx + 1 < num_reports => {
+            tui_state.report_idx += 1;
+        }
+        event::KeyCode::Right | event::KeyCode::Up => {
+            tui_state.report_idx = tui_state.report_idx.saturating_sub(1);
+        }
+        _ => {}
+    }
+}
 
 pub fn handle_key_event(key: event::KeyEvent, tui_state: &mut ui::TuiState, app: &mut App) {
     let log_key_event = |action: &str| {
@@ -13,6 +191,12 @@ pub fn handle_key_event(key: event::KeyEvent, tui_state: &mut ui::TuiState, app:
         )
     };
     match key.kind {
+        event::KeyEventKind::Press if tui_state.active_modal == ui::Modal::SearchObj => {
+            handle_search_key(key, tui_state, app)
+        }
+        event::KeyEventKind::Press if tui_state.active_modal == ui::Modal::CombatReport => {
+            handle_report_key(key, tui_state, app)
+        }
         event::KeyEventKind::Press => match key.code {
             event::KeyCode::Char('H') if tui_state.active_modal == ui::Modal::Help => {
                 tui_state.active_modal = ui::Modal::Off;
@@ -24,6 +208,178 @@ pub fn handle_key_event(key: event::KeyEvent, tui_state: &mut ui::TuiState, app:
                 log_key_event("show help");
                 return;
             }
+            event::KeyCode::Char('r') => {
+                log_key_event("open combat reports");
+                tui_state.report_idx = 0;
+                tui_state.active_modal = ui::Modal::CombatReport;
+            }
+            event::KeyCode::Char('/') => {
+                log_key_event("search");
+                tui_state.open_search(app);
+            }
+            event::KeyCode::Char(' ') => {
+                let mut speed = app.world.resource_mut::<resources::GameSpeed>();
+                speed.paused = !speed.paused;
+                log_key_event(if speed.paused { "pause" } else { "resume" });
+            }
+            event::KeyCode::Char(c @ ('1' | '2' | '5')) => {
+                let mut speed = app.world.resource_mut::<resources::GameSpeed>();
+                speed.paused = false;
+                speed.multiplier = c.to_digit(10).unwrap_or(1);
+                log_key_event("change speed");
+            }
+            event::KeyCode::Char('e')
+                if app.world.resource::<resources::Config>().mode == clock::GameMode::TurnBased =>
+            {
+                log_key_event("end turn");
+                app.world.resource_mut::<resources::EndTurn>().0 = true;
+                app.update();
+            }
+            event::KeyCode::F(5) => {
+                log_key_event("quicksave");
+                if let Err(err) =
+                    save::save_game(&mut app.world, &save::save_path(save::QUICKSAVE_FILE))
+                {
+                    log::error!("quicksave failed: {}", err);
+                }
+            }
+            event::KeyCode::F(9) => {
+                log_key_event("load latest save");
+                match save::latest_save() {
+                    Some(path) => match save::load_game(&mut app.world, &path) {
+                        Ok(()) => {
+                            // let the systems catch up with the new world, e.g. what the
+                            // player can see, before showing it
+                            app.update();
+                            tui_state.reload_world(app);
+                        }
+                        Err(err) => log::error!("failed to load {}: {}", path.display(), err),
+                    },
+                    None => log::warn!("no save found in {}", save::SAVE_DIR),
+                }
+            }
+            event::KeyCode::Enter if tui_state.active_view == ui::View::Galaxy => {
+                let Some(cell) = tui_state.galaxy_view.selected_astro_obj else {
+                    return;
+                };
+                match ui::star_at(app, cell) {
+                    Some(star) => {
+                        log_key_event("open star system");
+                        tui_state.system_view.star = Some(star);
+                        tui_state.system_view.selected_idx = 0;
+                        tui_state.active_view = ui::View::System;
+                    }
+                    None => log_key_event("no star system to open"),
+                }
+            }
+            event::KeyCode::Char('c' | 'l' | 'u' | 't' | 'T')
+                if tui_state.active_view == ui::View::System =>
+            {
+                log_key_event("cargo order");
+                handle_cargo_order(key.code, tui_state, app);
+            }
+            event::KeyCode::Char('p') if tui_state.active_view == ui::View::System => {
+                log_key_event("change structure");
+                tui_state.system_view.next_structure_kind();
+            }
+            event::KeyCode::Char('b') if tui_state.active_view == ui::View::System => {
+                log_key_event("build order");
+                handle_build_order(tui_state, app);
+            }
+            event::KeyCode::Char('x') if tui_state.active_view == ui::View::System => {
+                log_key_event("pipeline order");
+                handle_pipeline_order(tui_state, app);
+            }
+            event::KeyCode::Esc if tui_state.active_view == ui::View::System => {
+                log_key_event("back to galaxy");
+                tui_state.system_view.star = None;
+                tui_state.active_view = ui::View::Galaxy;
+            }
+            event::KeyCode::Down if tui_state.active_view == ui::View::System => {
+                let num_bodies = tui_state
+                    .system_view
+                    .star
+                    .map_or(0, |star| ui::query_system_bodies(app, star).len());
+                if tui_state.system_view.selected_idx + 1 < num_bodies {
+                    tui_state.system_view.selected_idx += 1;
+                }
+            }
+            event::KeyCode::Up if tui_state.active_view == ui::View::System => {
+                tui_state.system_view.selected_idx =
+                    tui_state.system_view.selected_idx.saturating_sub(1);
+            }
+            event::KeyCode::Tab
+            | event::KeyCode::BackTab
+            | event::KeyCode::Char('h' | 'j' | 'k' | 'l')
+                if tui_state.active_view == ui::View::Galaxy =>
+            {
+                let galaxy_view = &mut tui_state.galaxy_view;
+                match key.code {
+                    event::KeyCode::Tab => galaxy_view.select_next(),
+                    event::KeyCode::BackTab => galaxy_view.select_prev(),
+                    event::KeyCode::Char('h') => galaxy_view.select_direction((-1, 0)),
+                    event::KeyCode::Char('j') => galaxy_view.select_direction((0, -1)),
+                    event::KeyCode::Char('k') => galaxy_view.select_direction((0, 1)),
+                    _ => galaxy_view.select_direction((1, 0)),
+                }
+                log_key_event("change selection");
+                let galaxy_dimension = app.world.resource::<resources::Config>().galaxy_dimension;
+                galaxy_view.pan_to_selected(galaxy_dimension);
+            }
+            event::KeyCode::Char('s') => {
+                let ships = ui::query_local_ships(app);
+                tui_state.galaxy_view.select_next_ship(&ships);
+                tui_state.system_view.route_stops.clear();
+                log_key_event("select ship");
+                let Some(ship) = tui_state.galaxy_view.selected_ship else {
+                    return;
+                };
+                if tui_state.active_view != ui::View::Galaxy {
+                    return;
+                }
+                let galaxy_dimension = app.world.resource::<resources::Config>().galaxy_dimension;
+                if let Some(loc) = app.world.get::<cmp::Location>(ship) {
+                    let galaxy_view = &mut tui_state.galaxy_view;
+                    let canvas_point = galaxy_view
+                        .camera
+                        .grid_to_canvas((loc.x as f64, loc.y as f64), galaxy_dimension);
+                    galaxy_view.camera.pan_to_include(canvas_point, 4.);
+                }
+            }
+            event::KeyCode::Char('g') if tui_state.active_view == ui::View::Galaxy => {
+                let galaxy_view = &tui_state.galaxy_view;
+                let (Some(ship), Some(cell)) =
+                    (galaxy_view.selected_ship, galaxy_view.selected_astro_obj)
+                else {
+                    return;
+                };
+                let Some(star) = ui::star_at(app, cell) else {
+                    log_key_event("ships can only be sent to stars");
+                    return;
+                };
+                log_key_event("send ship");
+                tui_state.galaxy_view.order_message =
+                    match ships::set_course(&mut app.world, ship, star) {
+                        Ok(plan) if plan.strands => Some(format!(
+                            "Warning: no fuel left to reach a space port after {} cells",
+                            plan.path.len()
+                        )),
+                        Ok(plan) => Some(format!(
+                            "Course set: {} cells for {} fuel",
+                            plan.path.len(),
+                            plan.fuel_cost
+                        )),
+                        Err(err) => Some(format!("Order refused: {}", err)),
+                    };
+            }
+            event::KeyCode::Esc if tui_state.active_view == ui::View::Galaxy => {
+                log_key_event("deselect ship");
+                tui_state.galaxy_view.selected_ship = None;
+            }
+            event::KeyCode::Char('n') if tui_state.active_view == ui::View::Galaxy => {
+                log_key_event("toggle star names");
+                tui_state.galaxy_view.show_ids = !tui_state.galaxy_view.show_ids;
+            }
             event::KeyCode::Left if tui_state.active_view == ui::View::Galaxy => {
                 tui_state.galaxy_view.camera.origin.0 += 5.;
             }
@@ -36,57 +392,113 @@ pub fn handle_key_event(key: event::KeyEvent, tui_state: &mut ui::TuiState, app:
             event::KeyCode::Down if tui_state.active_view == ui::View::Galaxy => {
                 tui_state.galaxy_view.camera.origin.1 += 5.;
             }
-            event::KeyCode::Char('i') if tui_state.active_view == ui::View::Galaxy => {
-                tui_state.galaxy_view.camera.zoom_in();
-                // find the point on the canvas that is centered
-                // find the proportional point when scaled
-                // center the scaled point on the frame by moving the canvas origin by the difference between the new point and the frame center
-                // let old_scale = tui_state.galaxy_view.scale;
-                // if old_scale != 4. {
-                //     tui_state.galaxy_view.scale =
-                //         (tui_state.galaxy_view.scale + 0.25).clamp(1., 4.);
-                //     let old_center = (
-                //         tui_state.frame_size.0 as f64 / 2.,
-                //         tui_state.frame_size.1 as f64 / 2.,
-                //     );
-                //     let scaled_canvas_point = (
-                //         (old_center.0 - tui_state.galaxy_view.origin.0)
-                //             * tui_state.galaxy_view.scale,
-                //         (old_center.1 - tui_state.galaxy_view.origin.1)
-                //             * tui_state.galaxy_view.scale,
-                //     );
-                //     let origin = (
-                //         -(scaled_canvas_point.0 - old_center.0),
-                //         -(scaled_canvas_point.1 - old_center.1),
-                //     );
-                //     tui_state.galaxy_view.origin = origin;
-                // }
-            }
-            event::KeyCode::Char('o') if tui_state.active_view == ui::View::Galaxy => {
-                tui_state.galaxy_view.camera.zoom_out();
-                // let old_scale = tui_state.galaxy_view.scale;
-                // if old_scale != 1. {
-                //     tui_state.galaxy_view.scale =
-                //         (tui_state.galaxy_view.scale - 0.25).clamp(1., 4.);
-                //     let old_center = (
-                //         tui_state.frame_size.0 as f64 / 2.,
-                //         tui_state.frame_size.1 as f64 / 2.,
-                //     );
-                //     let scaled_canvas_point = (
-                //         (old_center.0 - tui_state.galaxy_view.origin.0)
-                //             * tui_state.galaxy_view.scale,
-                //         (old_center.1 - tui_state.galaxy_view.origin.1)
-                //             * tui_state.galaxy_view.scale,
-                //     );
-                //     let origin = (
-                //         -(scaled_canvas_point.0 - old_center.0),
-                //         -(scaled_canvas_point.1 - old_center.1),
-                //     );
-                //     tui_state.galaxy_view.origin = (0., 0.);
-                // }
+            event::KeyCode::Char('i' | 'o') if tui_state.active_view == ui::View::Galaxy => {
+                let galaxy_dimension = app.world.resource::<resources::Config>().galaxy_dimension;
+                let galaxy_view = &mut tui_state.galaxy_view;
+                let anchor = galaxy_view.zoom_anchor(galaxy_dimension);
+                if key.code == event::KeyCode::Char('i') {
+                    galaxy_view.camera.zoom_in(anchor);
+                } else {
+                    galaxy_view.camera.zoom_out(anchor);
+                }
+                log_key_event("zoom");
             }
             _ => {}
         },
         _ => {}
     }
 }
+
+/// Click to select the object nearest the cursor, drag to pan and scroll to zoom around
+/// the cursor. Only the galaxy canvas reacts to the mouse.
+pub fn handle_mouse_event(mouse: event::MouseEvent, tui_state: &mut ui::TuiState, app: &mut App) {
+    if tui_state.active_view != ui::View::Galaxy || tui_state.active_modal != ui::Modal::Off {
+        return;
+    }
+    let galaxy_dimension = app.world.resource::<resources::Config>().galaxy_dimension;
+    let galaxy_view = &mut tui_state.galaxy_view;
+    let position = (mouse.column, mouse.row);
+    match mouse.kind {
+        event::MouseEventKind::Down(event::MouseButton::Left) => {
+            galaxy_view.mouse_down = Some(position);
+            galaxy_view.dragged = false;
+        }
+        event::MouseEventKind::Drag(event::MouseButton::Left) => {
+            if let Some((column, row)) = galaxy_view.mouse_down {
+                galaxy_view.camera.pan_by_cells(
+                    mouse.column as i32 - column as i32,
+                    mouse.row as i32 - row as i32,
+                );
+                galaxy_view.mouse_down = Some(position);
+                galaxy_view.dragged = true;
+            }
+        }
+        event::MouseEventKind::Up(event::MouseButton::Left) => {
+            let clicked = galaxy_view.mouse_down.take().is_some() && !galaxy_view.dragged;
+            if let (true, Some(canvas_point)) =
+                (clicked, galaxy_view.camera.screen_to_canvas(position))
+            {
+                let grid_point = galaxy_view
+                    .camera
+                    .canvas_to_grid(canvas_point, galaxy_dimension);
+                galaxy_view.select_nearest(grid_point);
+                log::info!("mouse selected {:?}", galaxy_view.selected_astro_obj);
+            }
+        }
+        event::MouseEventKind::ScrollUp | event::MouseEventKind::ScrollDown => {
+            let Some(anchor) = galaxy_view.camera.screen_to_canvas(position) else {
+                return;
+            };
+            if mouse.kind == event::MouseEventKind::ScrollUp {
+                galaxy_view.camera.zoom_in(anchor);
+            } else {
+                galaxy_view.camera.zoom_out(anchor);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use tui::layout::Rect;
+
+    fn press(code: event::KeyCode, tui_state: &mut ui::TuiState, app: &mut App) {
+        let key = event::KeyEvent::new(code, event::KeyModifiers::NONE);
+        handle_key_event(key, tui_state, app);
+    }
+
+    #[test]
+    fn test_search_jumps_to_ship_in_open_space() {
+        let mut app = App::new();
+        app.init_resource::<resources::Config>();
+        app.world.spawn((
+            cmp::astronomy::GalacticObj::Star,
+            cmp::Location::at(1, 1),
+            cmp::Name("Sol".to_string()),
+        ));
+        let ship = app
+            .world
+            .spawn((ships::scout((7, 9)), cmp::Name("Raider".to_string())))
+            .id();
+        let mut tui_state = ui::TuiState::new(&mut app);
+        tui_state.resize(Rect::new(0, 0, 80, 30));
+        tui_state.open_search(&mut app);
+        for c in "raider".chars() {
+            press(event::KeyCode::Char(c), &mut tui_state, &mut app);
+        }
+        press(event::KeyCode::Enter, &mut tui_state, &mut app);
+
+        assert!(tui_state.active_modal == ui::Modal::Off);
+        let galaxy_view = &tui_state.galaxy_view;
+        assert_eq!(galaxy_view.selected_ship, Some(ship));
+        let galaxy_dimension = app.world.resource::<resources::Config>().galaxy_dimension;
+        let camera = &galaxy_view.camera;
+        let (x, y) = camera.grid_to_canvas((7., 9.), galaxy_dimension);
+        assert!((x - camera.frame_center.0).abs() < 1e-9);
+        assert!((y - camera.frame_center.1).abs() < 1e-9);
+    }
+}
@@ -1,7 +1,22 @@
 mod bundles;
+mod cargo;
+mod cli;
+mod clock;
+mod combat;
 mod components;
+mod construction;
+mod faction;
+mod fog;
+mod galaxy;
 mod keymaps;
+mod pipelines;
 mod resources;
+mod save;
+mod search;
+mod ships;
+mod star_system;
+#[cfg(test)]
+mod test_utils;
 mod ui;
 mod utilities;
 
@@ -12,6 +27,7 @@ use crossterm::{
 };
 use rand::Rng;
 use std::io::Write;
+use std::time::Instant;
 use std::{collections::HashSet, io};
 use tui::{
     backend::{Backend, CrosstermBackend},
@@ -71,13 +87,53 @@ fn main() {
         })
         .init();
     log::info!("~~~ welcome to STARS ~~~");
+    // load the config before the terminal enters raw mode, so problems can be printed plainly
+    let config = match cli::CliArgs::parse(std::env::args().skip(1)) {
+        Ok(cli_args) if cli_args.help => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Ok(cli_args) => resources::Config::load(&cli_args),
+        Err(err) => Err(err),
+    };
+    let config = match config {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
     log::info!("creating bevy app");
-    App::new()
-        .set_runner(runner)
-        .init_resource::<resources::Config>()
+    let mut app = App::new();
+    clock::add_simulation(&mut app);
+    app.set_runner(runner)
+        .insert_resource(config)
+        .init_resource::<resources::GalaxySeed>()
+        .init_resource::<resources::GalaxyRng>()
         .init_resource::<resources::NameGenerator>()
+        .init_resource::<resources::GameSpeed>()
+        .init_resource::<fog::FogOfWar>()
+        .init_resource::<combat::CombatLog>()
         // .add_plugins(MinimalPlugins)
         .add_startup_system(spawn_galaxy)
+        .add_system(fog::update_fog_of_war)
+        .add_system_to_stage(clock::SimulationStage, ships::move_ships)
+        .add_system_to_stage(clock::SimulationStage, ships::refuel_ships)
+        .add_system_to_stage(clock::SimulationStage, ships::repair_ships)
+        .add_system_to_stage(
+            clock::SimulationStage,
+            cargo::follow_trade_routes.after(ships::move_ships),
+        )
+        .add_system_to_stage(
+            clock::SimulationStage,
+            combat::start_battles.after(ships::move_ships),
+        )
+        .add_system_to_stage(
+            clock::SimulationStage,
+            combat::recharge_shields.after(combat::start_battles),
+        )
+        .add_system_to_stage(clock::SimulationStage, construction::build_structures)
+        .add_system_to_stage(clock::SimulationStage, pipelines::pump_resources)
         .run();
 }
 
@@ -117,60 +173,91 @@ fn loop_game<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result
 
     log::info!("initializing ui");
     let mut tui_state = ui::TuiState::new(&mut app);
-    terminal.draw(|f| {
-        tui_state.galaxy_view.camera.frame_size = (f.size().width as f64, f.size().height as f64);
-        tui_state.galaxy_view.camera.update();
-    })?;
+    tui_state.resize(terminal.size()?);
 
     log::info!("beginning game loop");
+    let mut timer = clock::TickTimer::default();
+    let mut last_frame = Instant::now();
     loop {
-        log::info!("drawing ui");
-
+        log::trace!("drawing ui");
         terminal.draw(|f| ui::ui(f, &mut tui_state, &mut app))?;
-        log::info!("reading input");
-        if let Event::Key(key) = event::read()? {
-            if key.code == KeyCode::Char('q') {
+
+        // wait for input only until the next tick is due, so the simulation keeps running
+        // while the player watches
+        // in turn-based mode the world only changes when a turn ends, so there are no ticks
+        let config = app.world.resource::<Config>();
+        let tick_length = match config.mode {
+            clock::GameMode::RealTime => {
+                let tick_rate = config.tick_rate;
+                app.world.resource::<GameSpeed>().tick_length(tick_rate)
+            }
+            clock::GameMode::TurnBased => None,
+        };
+        let timeout = tick_length.map_or(clock::IDLE_POLL, |tick_length| {
+            timer.time_to_next_tick(tick_length)
+        });
+        let event = if event::poll(timeout)? {
+            Some(event::read()?)
+        } else {
+            None
+        };
+        match event {
+            Some(Event::Key(key))
+                if key.code == KeyCode::Char('q')
+                    && tui_state.active_modal != ui::Modal::SearchObj =>
+            {
                 log::info!("quitting game");
-                // TODO: autosave
+                if let Err(err) =
+                    save::save_game(&mut app.world, &save::save_path(save::AUTOSAVE_FILE))
+                {
+                    log::error!("autosave failed: {}", err);
+                }
                 return Ok(());
             }
-            keymaps::handle_key_event(key, &mut tui_state, &mut app);
+            Some(Event::Key(key)) => keymaps::handle_key_event(key, &mut tui_state, &mut app),
+            Some(Event::Mouse(mouse)) => {
+                keymaps::handle_mouse_event(mouse, &mut tui_state, &mut app)
+            }
+            Some(Event::Resize(width, height)) => {
+                tui_state.resize(tui::layout::Rect::new(0, 0, width, height))
+            }
+            _ => {}
         }
 
-        log::info!("updating bevy");
-        app.update();
+        let now = Instant::now();
+        let elapsed = now - last_frame;
+        last_frame = now;
+        if let Some(tick_length) = tick_length {
+            for _ in 0..timer.advance(elapsed, tick_length) {
+                log::trace!("updating bevy");
+                app.update();
+            }
+        }
     }
 }
 
-fn spawn_galaxy(
-    mut commands: Commands,
-    config: Res<Config>,
-    mut name_generator: ResMut<NameGenerator>,
-) {
-    // TODO: add randomness and pre-made patterns
-    let mut rng = thread_rng();
-    let mut star_count = 0;
-    let mut used_dimensions = HashSet::new();
-    while star_count < config.num_stars {
-        let x = rng.gen_range(0..config.galaxy_dimension);
-        let y = rng.gen_range(0..config.galaxy_dimension);
-        if used_dimensions.contains(&(x, y)) {
-            continue;
-        }
-        used_dimensions.insert((x, y));
+fn random_ui_offset<R: Rng>(rng: &mut R) -> (f32, f32) {
+    let choices = [0.25, 0.5, 1.];
+    let weights = [3, 2, 1];
+    let dist = rand::distributions::WeightedIndex::new(weights).unwrap();
+    let is_negative = if rng.gen_bool(0.5) { -1.0 } else { 1.0 };
 
-        // get ui offset
-        let choices = [0.25, 0.5, 1.];
-        let weights = [3, 2, 1];
-        let dist = rand::distributions::WeightedIndex::new(&weights).unwrap();
-        let is_negative = if rng.gen_bool(0.5) { -1.0 } else { 1.0 };
+    (
+        choices[dist.sample(rng)] * is_negative,
+        choices[dist.sample(rng)] * is_negative,
+    )
+}
 
-        let ui_offset = (
-            choices[dist.sample(&mut rng)] * is_negative,
-            choices[dist.sample(&mut rng)] * is_negative,
-        );
-        let star_name = name_generator.random_name();
-        commands.spawn((
+fn spawn_galactic_obj(
+    commands: &mut Commands,
+    galactic_obj: components::astronomy::GalacticObj,
+    (x, y): (u32, u32),
+    ui_offset: (f32, f32),
+    name: String,
+) -> Entity {
+    log::trace!("spawned {:?} {} at ({}, {})", galactic_obj, name, x, y);
+    commands
+        .spawn((
             components::Location {
                 x,
                 y,
@@ -178,13 +265,125 @@ fn spawn_galaxy(
                 z: 0,
                 ui_offset,
             },
-            components::astronomy::GalacticObj::Star,
-            components::Name(star_name.clone()),
-        ));
-        star_count += 1;
-        log::trace!("spawned star {} at ({}, {})", star_name, x, y);
+            galactic_obj,
+            components::Name(name),
+        ))
+        .id()
+}
+
+fn spawn_galaxy(
+    mut commands: Commands,
+    config: Res<Config>,
+    mut name_generator: ResMut<NameGenerator>,
+    mut galaxy_rng: ResMut<GalaxyRng>,
+) {
+    use components::astronomy::GalacticObj;
+
+    let rng = &mut galaxy_rng.0;
+    let mut occupied = HashSet::new();
+
+    let core = galaxy::galactic_core(config.galaxy_dimension);
+    occupied.insert(core);
+    let name = name_generator.random_name(rng);
+    spawn_galactic_obj(
+        &mut commands,
+        GalacticObj::SupermassiveBlackHole,
+        core,
+        (0., 0.),
+        name,
+    );
+
+    log::info!("placing stars in a {:?} galaxy", config.shape);
+    let positions = galaxy::place_stars(
+        &config.shape,
+        config.num_stars,
+        config.galaxy_dimension,
+        &mut occupied,
+        rng,
+    );
+    let num_stars = positions.len();
+    let mut homeworld_candidates = Vec::new();
+    let mut with_planets = 0;
+    for (idx, cell) in positions.into_iter().enumerate() {
+        let ui_offset = random_ui_offset(rng);
+        let name = name_generator.random_name(rng);
+        let star = spawn_galactic_obj(
+            &mut commands,
+            GalacticObj::Star,
+            cell,
+            ui_offset,
+            name.clone(),
+        );
+        let location = components::Location {
+            x: cell.0,
+            y: cell.1,
+            z: 0,
+            w: 0,
+            ui_offset,
+        };
+        // every player needs a star system with a planet for a homeworld, so once the
+        // stars left are only just enough, they all get one
+        let homeworlds_needed = (config.num_players as usize).saturating_sub(with_planets);
+        let min_planets = u32::from(homeworlds_needed >= num_stars - idx);
+        let planets = star_system::spawn_star_system(
+            &mut commands,
+            star,
+            &name,
+            &location,
+            min_planets,
+            config.max_planets_per_star,
+            rng,
+        );
+        if !planets.is_empty() {
+            with_planets += 1;
+        }
+        homeworld_candidates.push(faction::HomeworldCandidate {
+            star,
+            cell,
+            planets,
+        });
     }
-    log::info!("spawned {} stars", config.num_stars);
+    log::info!("spawned {} stars", num_stars);
+
+    let players = faction::spawn_players(
+        &mut commands,
+        &homeworld_candidates,
+        config.num_players,
+        rng,
+    );
+    // the first player is the one at the keyboard
+    if let Some(&player) = players.first() {
+        commands.insert_resource(LocalPlayer(player));
+    }
+
+    let num_cells = config.galaxy_dimension.pow(2) as f64;
+    let num_black_holes = (num_cells * config.black_hole_density).round() as u32;
+    let black_holes = galaxy::scatter(num_black_holes, config.galaxy_dimension, &mut occupied, rng);
+    log::info!("spawned {} black holes", black_holes.len());
+    for cell in black_holes {
+        let ui_offset = random_ui_offset(rng);
+        let name = name_generator.random_name(rng);
+        spawn_galactic_obj(&mut commands, GalacticObj::BlackHole, cell, ui_offset, name);
+    }
+
+    let num_nebulae = (num_cells * config.nebula_density).round() as u32;
+    for _ in 0..num_nebulae {
+        let size = rng.gen_range(2..=config.max_nebula_cells.max(2));
+        let cells = galaxy::grow_nebula(size, config.galaxy_dimension, &mut occupied, rng);
+        // every cell of a nebula shares its name
+        let name = name_generator.random_name(rng);
+        for cell in cells {
+            let ui_offset = random_ui_offset(rng);
+            spawn_galactic_obj(
+                &mut commands,
+                GalacticObj::Nebula,
+                cell,
+                ui_offset,
+                name.clone(),
+            );
+        }
+    }
+    log::info!("spawned {} nebulae", num_nebulae);
 }
 
 #[cfg(test)]
@@ -203,17 +402,139 @@ mod tests {
         assert!(config.is_some());
     }
 
-    #[test]
-    fn test_spawn_galaxy() {
+    fn galaxy_app(seed: u64) -> App {
+        let names = (0..100).map(|i| format!("Star{}", i)).collect();
         let mut app = App::new();
-        app.init_resource::<Config>()
+        app.insert_resource(GalaxySeed(seed))
+            .insert_resource(NameGenerator::from_names(names))
+            .init_resource::<Config>()
+            .init_resource::<GalaxyRng>()
             .add_plugins(MinimalPlugins)
             .add_startup_system(spawn_galaxy);
+        app
+    }
+
+    fn galaxy_snapshot(app: &mut App) -> Vec<(String, u32, u32, (f32, f32))> {
+        let mut snapshot: Vec<_> = app
+            .world
+            .query::<(&components::Name, &components::Location)>()
+            .iter(&app.world)
+            .map(|(name, loc)| (name.0.clone(), loc.x, loc.y, loc.ui_offset))
+            .collect();
+        snapshot.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        snapshot
+    }
+
+    #[test]
+    fn test_spawn_galaxy() {
+        let mut app = galaxy_app(0);
 
         let mut galactic_obj_query = app.world.query::<&components::astronomy::GalacticObj>();
         assert_eq!(galactic_obj_query.iter(&app.world).count(), 0);
 
         app.update();
-        assert_eq!(galactic_obj_query.iter(&app.world).count(), 100);
+        let config = app.world.resource::<Config>().clone();
+        let count = |app: &mut App, kind: components::astronomy::GalacticObj| {
+            app.world
+                .query::<&components::astronomy::GalacticObj>()
+                .iter(&app.world)
+                .filter(|obj| **obj == kind)
+                .count()
+        };
+        assert_eq!(
+            count(&mut app, components::astronomy::GalacticObj::Star),
+            config.num_stars as usize
+        );
+        assert_eq!(
+            count(&mut app, components::astronomy::GalacticObj::BlackHole),
+            (config.galaxy_dimension.pow(2) as f64 * config.black_hole_density).round() as usize
+        );
+        assert!(count(&mut app, components::astronomy::GalacticObj::Nebula) > 0);
+
+        let core = galaxy::galactic_core(config.galaxy_dimension);
+        let smbh: Vec<(u32, u32)> = app
+            .world
+            .query::<(&components::astronomy::GalacticObj, &components::Location)>()
+            .iter(&app.world)
+            .filter(|(obj, _)| **obj == components::astronomy::GalacticObj::SupermassiveBlackHole)
+            .map(|(_, loc)| (loc.x, loc.y))
+            .collect();
+        assert_eq!(smbh, vec![core]);
+
+        let players: Vec<(Entity, Option<Entity>)> = app
+            .world
+            .query::<(Entity, &components::Player)>()
+            .iter(&app.world)
+            .map(|(entity, player)| (entity, player.homeworld))
+            .collect();
+        assert_eq!(players.len(), config.num_players as usize);
+        for (player, homeworld) in players {
+            let homeworld = homeworld.expect("every player gets a homeworld");
+            assert_eq!(
+                app.world.get::<components::Owner>(homeworld),
+                Some(&components::Owner(player))
+            );
+            let star = app.world.get::<Parent>(homeworld).unwrap().get();
+            assert_eq!(
+                app.world.get::<components::Owner>(star),
+                Some(&components::Owner(player))
+            );
+        }
+        // and a scout to start exploring with
+        let ships = app
+            .world
+            .query_filtered::<&components::Owner, With<components::ship::Engine>>()
+            .iter(&app.world)
+            .count();
+        assert_eq!(ships, config.num_players as usize);
+    }
+
+    #[test]
+    fn test_every_player_gets_a_homeworld() {
+        // as many players as stars, and few planets to go around
+        for seed in 0..10 {
+            let mut app = galaxy_app(seed);
+            app.insert_resource(Config {
+                num_stars: 4,
+                num_players: 4,
+                max_planets_per_star: 1,
+                ..Config::default()
+            });
+            app.update();
+            let homeworlds: HashSet<Entity> = app
+                .world
+                .query::<&components::Player>()
+                .iter(&app.world)
+                .filter_map(|player| player.homeworld)
+                .collect();
+            assert_eq!(homeworlds.len(), 4, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn test_no_shared_cells() {
+        let mut app = galaxy_app(5);
+        app.update();
+        let cells: Vec<(u32, u32)> = app
+            .world
+            .query_filtered::<&components::Location, With<components::astronomy::GalacticObj>>()
+            .iter(&app.world)
+            .map(|loc| (loc.x, loc.y))
+            .collect();
+        let unique: HashSet<_> = cells.iter().collect();
+        assert_eq!(unique.len(), cells.len());
+    }
+
+    #[test]
+    fn test_same_seed_same_galaxy() {
+        let mut first = galaxy_app(42);
+        let mut second = galaxy_app(42);
+        first.update();
+        second.update();
+        assert_eq!(galaxy_snapshot(&mut first), galaxy_snapshot(&mut second));
+
+        let mut other = galaxy_app(43);
+        other.update();
+        assert_ne!(galaxy_snapshot(&mut first), galaxy_snapshot(&mut other));
     }
 }
@@ -0,0 +1,250 @@
+use std::{collections::BTreeMap, fmt};
+
+use bevy::prelude::*;
+
+use crate::{
+    components::{self as cmp, structure::PipelineLink, Cargo, Structure},
+    ships,
+};
+
+/// Most resources a pipeline moves per tick.
+pub const PIPELINE_CAPACITY: u32 = 10;
+/// Longest a pipeline can be, in cells.
+pub const MAX_PIPELINE_LENGTH: u32 = 8;
+
+/// Why two planets can't be connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelineError {
+    NoPlanet,
+    NotOwned,
+    /// The planet has no pipeline that isn't already connected.
+    NoPipeline,
+    SameSystem,
+    TooLong {
+        length: u32,
+    },
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PipelineError::NoPlanet => write!(f, "no such planet"),
+            PipelineError::NotOwned => write!(f, "both planets must be yours"),
+            PipelineError::NoPipeline => write!(f, "build a pipeline there first"),
+            PipelineError::SameSystem => write!(f, "pipelines connect different star systems"),
+            PipelineError::TooLong { length } => write!(
+                f,
+                "{} cells is longer than a pipeline reaches ({})",
+                length, MAX_PIPELINE_LENGTH
+            ),
+        }
+    }
+}
+
+fn cell(world: &World, entity: Entity) -> Option<(u32, u32)> {
+    world.get::<cmp::Location>(entity).map(|loc| (loc.x, loc.y))
+}
+
+fn owner(world: &World, entity: Entity) -> Option<Entity> {
+    world.get::<cmp::Owner>(entity).map(|owner| owner.0)
+}
+
+/// Connect a free pipeline on `from` to `to`, so it starts pumping resources there.
+pub fn connect(
+    world: &mut World,
+    player: Entity,
+    from: Entity,
+    to: Entity,
+) -> Result<(), PipelineError> {
+    let from_cell = cell(world, from).ok_or(PipelineError::NoPlanet)?;
+    let to_cell = cell(world, to).ok_or(PipelineError::NoPlanet)?;
+    if owner(world, from) != Some(player) || owner(world, to) != Some(player) {
+        return Err(PipelineError::NotOwned);
+    }
+    if from_cell == to_cell {
+        return Err(PipelineError::SameSystem);
+    }
+    let length = ships::distance(from_cell, to_cell);
+    if length > MAX_PIPELINE_LENGTH {
+        return Err(PipelineError::TooLong { length });
+    }
+    let pipeline = world
+        .get::<Children>(from)
+        .into_iter()
+        .flatten()
+        .copied()
+        .find(|&child| {
+            world.get::<Structure>(child) == Some(&Structure::Pipeline)
+                && owner(world, child) == Some(player)
+                && world.get::<PipelineLink>(child).is_none()
+        })
+        .ok_or(PipelineError::NoPipeline)?;
+    log::info!("pipeline {:?} connects {:?} to {:?}", pipeline, from, to);
+    world.entity_mut(pipeline).insert(PipelineLink {
+        to,
+        capacity: PIPELINE_CAPACITY,
+        flow: 0,
+        disrupted: false,
+    });
+    Ok(())
+}
+
+/// Move up to `capacity` resources from the stockpile of `from` to that of `to`. Returns
+/// how much was moved.
+fn transfer(world: &mut World, from: Entity, to: Entity, capacity: u32) -> u32 {
+    let stock = world
+        .get::<cmp::Stockpile>(from)
+        .and_then(|stockpile| stockpile.0.get(&Cargo::Resources).copied())
+        .unwrap_or(0);
+    let moved = capacity.min(stock);
+    if moved == 0 {
+        return 0;
+    }
+    if let Some(mut stockpile) = world.get_mut::<cmp::Stockpile>(from) {
+        if let Some(stock) = stockpile.0.get_mut(&Cargo::Resources) {
+            *stock -= moved;
+        }
+    }
+    let mut to_entity = world.entity_mut(to);
+    match to_entity.get_mut::<cmp::Stockpile>() {
+        Some(mut stockpile) => *stockpile.0.entry(Cargo::Resources).or_default() += moved,
+        None => {
+            to_entity.insert(cmp::Stockpile(BTreeMap::from([(Cargo::Resources, moved)])));
+        }
+    }
+    moved
+}
+
+/// Every connected pipeline pumps what it can. A pipeline stops while either of its ends
+/// belongs to someone other than its owner, and picks up again once both are back.
+pub fn pump_resources(world: &mut World) {
+    let pipelines: Vec<(Entity, Entity, Entity, PipelineLink)> = world
+        .query::<(Entity, &cmp::Owner, &Parent, &PipelineLink)>()
+        .iter(world)
+        .map(|(pipeline, owner, planet, link)| (pipeline, owner.0, planet.get(), *link))
+        .collect();
+    for (pipeline, pipeline_owner, from, link) in pipelines {
+        let intact = owner(world, from) == Some(pipeline_owner)
+            && owner(world, link.to) == Some(pipeline_owner);
+        if intact == link.disrupted {
+            if intact {
+                log::info!("pipeline {:?} is flowing again", pipeline);
+            } else {
+                log::warn!("pipeline {:?} is disrupted", pipeline);
+            }
+        }
+        let flow = if intact {
+            transfer(world, from, link.to, link.capacity)
+        } else {
+            0
+        };
+        world.entity_mut(pipeline).insert(PipelineLink {
+            flow,
+            disrupted: !intact,
+            ..link
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::test_utils;
+
+    fn spawn_stocked_planet(world: &mut World, x: u32, owner: Entity, resources: u32) -> Entity {
+        let planet = test_utils::spawn_planet(world, (x, 0), Some(owner));
+        world
+            .entity_mut(planet)
+            .insert(cmp::Stockpile(BTreeMap::from([(
+                Cargo::Resources,
+                resources,
+            )])));
+        planet
+    }
+
+    fn resources(world: &World, planet: Entity) -> u32 {
+        world.get::<cmp::Stockpile>(planet).unwrap().0[&Cargo::Resources]
+    }
+
+    #[test]
+    fn test_pipeline_pumps_until_captured() {
+        let mut app = App::new();
+        app.add_system(pump_resources);
+        let world = &mut app.world;
+        let player = world.spawn_empty().id();
+        let mine = spawn_stocked_planet(world, 0, player, 25);
+        let colony = spawn_stocked_planet(world, 3, player, 0);
+        let faraway = spawn_stocked_planet(world, MAX_PIPELINE_LENGTH + 1, player, 0);
+        assert_eq!(
+            connect(world, player, mine, colony),
+            Err(PipelineError::NoPipeline)
+        );
+        world.entity_mut(mine).with_children(|planet| {
+            planet.spawn((Structure::Pipeline, cmp::Owner(player)));
+        });
+        assert_eq!(
+            connect(world, player, mine, faraway),
+            Err(PipelineError::TooLong {
+                length: MAX_PIPELINE_LENGTH + 1
+            })
+        );
+        assert_eq!(connect(world, player, mine, colony), Ok(()));
+        // the only pipeline is taken
+        let outpost = spawn_stocked_planet(world, 5, player, 0);
+        assert_eq!(
+            connect(world, player, mine, outpost),
+            Err(PipelineError::NoPipeline)
+        );
+
+        // capacity-limited until the mine runs dry
+        for _ in 0..3 {
+            app.update();
+        }
+        assert_eq!(resources(&app.world, colony), 25);
+        assert_eq!(resources(&app.world, mine), 0);
+        let link = app.world.query::<&PipelineLink>().single(&app.world);
+        assert_eq!((link.flow, link.disrupted), (5, false));
+
+        // a rival takes the colony and the flow stops
+        let rival = app.world.spawn_empty().id();
+        app.world.entity_mut(colony).insert(cmp::Owner(rival));
+        app.world
+            .get_mut::<cmp::Stockpile>(mine)
+            .unwrap()
+            .0
+            .insert(Cargo::Resources, 30);
+        app.update();
+        assert_eq!(resources(&app.world, mine), 30);
+        let link = app.world.query::<&PipelineLink>().single(&app.world);
+        assert_eq!((link.flow, link.disrupted), (0, true));
+
+        // and picks up again once it's taken back
+        app.world.entity_mut(colony).insert(cmp::Owner(player));
+        app.update();
+        assert_eq!(resources(&app.world, colony), 25 + PIPELINE_CAPACITY);
+    }
+
+    #[test]
+    fn test_dry_pipeline_leaves_stockpiles_alone() {
+        let mut app = App::new();
+        app.add_system(pump_resources);
+        let world = &mut app.world;
+        let player = world.spawn_empty().id();
+        let mine = spawn_stocked_planet(world, 0, player, 0);
+        world
+            .entity_mut(mine)
+            .insert(cmp::Stockpile(BTreeMap::from([(Cargo::Probes, 3)])));
+        let colony = spawn_stocked_planet(world, 3, player, 0);
+        world.entity_mut(mine).with_children(|planet| {
+            planet.spawn((Structure::Pipeline, cmp::Owner(player)));
+        });
+        assert_eq!(connect(world, player, mine, colony), Ok(()));
+
+        app.update();
+        let stockpile = app.world.get::<cmp::Stockpile>(mine).unwrap();
+        assert_eq!(stockpile.0, BTreeMap::from([(Cargo::Probes, 3)]));
+    }
+}
@@ -2,67 +2,378 @@ use bevy::prelude::*;
 use log;
 use rand::prelude::*;
 use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs;
-use std::path;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-#[derive(Resource)]
+use crate::cli::CliArgs;
+use crate::clock::GameMode;
+use crate::galaxy::GalaxyShape;
+
+pub const DEFAULT_CONFIG_FILE: &str = "stars.ron";
+
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     pub galaxy_dimension: u32,
     pub num_stars: u32,
+    pub seed: Option<u64>,
+    pub shape: GalaxyShape,
+    /// Fraction of the grid's cells holding a black hole.
+    pub black_hole_density: f64,
+    /// Fraction of the grid's cells a nebula starts in.
+    pub nebula_density: f64,
+    pub max_nebula_cells: u32,
+    pub max_planets_per_star: u32,
+    pub num_players: u32,
+    /// Hide what the local player's sensors haven't seen.
+    pub fog_of_war: bool,
+    /// Simulation ticks per second at normal speed.
+    pub tick_rate: f64,
+    pub mode: GameMode,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            galaxy_dimension: 25,
+            num_stars: 50,
+            seed: None,
+            shape: GalaxyShape::default(),
+            black_hole_density: 0.01,
+            nebula_density: 0.004,
+            max_nebula_cells: 6,
+            max_planets_per_star: 8,
+            num_players: 2,
+            fog_of_war: true,
+            tick_rate: 4.,
+            mode: GameMode::default(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ValidationError {
+    ZeroGalaxyDimension,
+    NoStars,
+    TooManyStars { num_stars: u32, max_stars: u32 },
+    ZeroSpiralArms,
+    ZeroClusters,
+    InvalidDensity { field: &'static str, value: f64 },
+    InvalidTickRate(f64),
+    NoPlayers,
+    TooManyPlayers { num_players: u32, num_stars: u32 },
+    NoPlanets,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::ZeroGalaxyDimension => {
+                write!(f, "galaxy_dimension must be greater than 0")
+            }
+            ValidationError::NoStars => write!(f, "num_stars must be greater than 0"),
+            ValidationError::TooManyStars {
+                num_stars,
+                max_stars,
+            } => write!(
+                f,
+                "num_stars is {} but a galaxy this size only fits {} (galaxy_dimension^2 - 1)",
+                num_stars, max_stars
+            ),
+            ValidationError::ZeroSpiralArms => write!(f, "a spiral galaxy needs at least one arm"),
+            ValidationError::ZeroClusters => {
+                write!(f, "a clustered galaxy needs at least one cluster")
+            }
+            ValidationError::InvalidDensity { field, value } => {
+                write!(f, "{} is {} but must be between 0 and 1", field, value)
+            }
+            ValidationError::NoPlayers => write!(f, "num_players must be greater than 0"),
+            ValidationError::TooManyPlayers {
+                num_players,
+                num_stars,
+            } => write!(
+                f,
+                "num_players is {} but every player needs a star and there are only {}",
+                num_players, num_stars
+            ),
+            ValidationError::NoPlanets => write!(
+                f,
+                "max_planets_per_star must be greater than 0 for players to have homeworlds"
+            ),
+            ValidationError::InvalidTickRate(tick_rate) => write!(
+                f,
+                "tick_rate is {} but must be between {} and {}",
+                tick_rate,
+                TICK_RATE_RANGE.start(),
+                TICK_RATE_RANGE.end()
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        err: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        err: ron::error::SpannedError,
+    },
+    UnknownArgument(String),
+    MissingValue(String),
+    InvalidValue {
+        flag: String,
+        value: String,
+    },
+    Invalid(Vec<ValidationError>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, err } => {
+                write!(f, "could not read config {}: {}", path.display(), err)
+            }
+            ConfigError::Parse { path, err } => {
+                write!(f, "could not parse config {}: {}", path.display(), err)
+            }
+            ConfigError::UnknownArgument(arg) => write!(f, "unknown argument {:?}", arg),
+            ConfigError::MissingValue(flag) => write!(f, "{} expects a value", flag),
+            ConfigError::InvalidValue { flag, value } => {
+                write!(f, "invalid value {:?} for {}", value, flag)
+            }
+            ConfigError::Invalid(errors) => {
+                write!(f, "invalid config:")?;
+                for err in errors {
+                    write!(f, "\n  - {}", err)?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
 impl Config {
-    pub fn validate(self) -> Self {
-        if self.num_stars > self.galaxy_dimension.pow(2) {
-            panic!("num_stars must be less than galaxy_dimension^2");
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+        if self.galaxy_dimension == 0 {
+            errors.push(ValidationError::ZeroGalaxyDimension);
+        }
+        if self.num_stars == 0 {
+            errors.push(ValidationError::NoStars);
+        }
+        // one cell is always taken by the supermassive black hole at the core
+        let max_stars = self.galaxy_dimension.saturating_pow(2).saturating_sub(1);
+        if self.num_stars > max_stars {
+            errors.push(ValidationError::TooManyStars {
+                num_stars: self.num_stars,
+                max_stars,
+            });
+        }
+        if self.num_players == 0 {
+            errors.push(ValidationError::NoPlayers);
+        } else if self.num_players > self.num_stars {
+            errors.push(ValidationError::TooManyPlayers {
+                num_players: self.num_players,
+                num_stars: self.num_stars,
+            });
         }
-        self
+        if self.num_players > 0 && self.max_planets_per_star == 0 {
+            errors.push(ValidationError::NoPlanets);
+        }
+        for (field, value) in [
+            ("black_hole_density", self.black_hole_density),
+            ("nebula_density", self.nebula_density),
+        ] {
+            if !(0.0..=1.0).contains(&value) {
+                errors.push(ValidationError::InvalidDensity { field, value });
+            }
+        }
+        if !TICK_RATE_RANGE.contains(&self.tick_rate) {
+            errors.push(ValidationError::InvalidTickRate(self.tick_rate));
+        }
+        match self.shape {
+            GalaxyShape::Spiral { arms: 0 } => errors.push(ValidationError::ZeroSpiralArms),
+            GalaxyShape::Clustered { clusters: 0 } => errors.push(ValidationError::ZeroClusters),
+            _ => {}
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(errors))
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(|err| ConfigError::Io {
+            path: path.to_path_buf(),
+            err,
+        })?;
+        ron::from_str(&contents).map_err(|err| ConfigError::Parse {
+            path: path.to_path_buf(),
+            err,
+        })
+    }
+
+    /// Build the config for this run: the file given with `--config` (or `stars.ron` if
+    /// it exists), then any command-line overrides on top, validated as a whole.
+    pub fn load(args: &CliArgs) -> Result<Config, ConfigError> {
+        let mut config = match &args.config_path {
+            Some(path) => Config::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Config::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Config::default(),
+        };
+        args.apply(&mut config);
+        config.validate()?;
+        log::info!("loaded config {:?}", config);
+        Ok(config)
     }
 }
 
-impl FromWorld for Config {
-    fn from_world(world: &mut World) -> Self {
-        log::info!("creating config");
-        Config {
-            galaxy_dimension: 25,
-            num_stars: 50,
+/// Tick rates the main loop can keep to: slower ticks would be hours apart and faster
+/// ones shorter than a frame.
+pub const TICK_RATE_RANGE: RangeInclusive<f64> = 0.01..=1000.;
+
+/// How fast the simulation runs relative to `Config::tick_rate`.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GameSpeed {
+    pub paused: bool,
+    pub multiplier: u32,
+}
+
+impl Default for GameSpeed {
+    fn default() -> Self {
+        GameSpeed {
+            paused: false,
+            multiplier: 1,
         }
-        .validate()
     }
 }
 
+impl GameSpeed {
+    /// Real time between two ticks at this speed, or `None` while paused or when the tick
+    /// rate makes no sense as a duration.
+    pub fn tick_length(&self, tick_rate: f64) -> Option<Duration> {
+        if self.paused || self.multiplier == 0 {
+            return None;
+        }
+        Duration::try_from_secs_f64(1. / (tick_rate * self.multiplier as f64)).ok()
+    }
+}
+
+impl fmt::Display for GameSpeed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.paused {
+            write!(f, "paused")
+        } else {
+            write!(f, "{}x", self.multiplier)
+        }
+    }
+}
+
+/// Number of simulation ticks run so far.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Tick(pub u64);
+
+/// The current turn in turn-based mode, counting from 0 before the first turn ends.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TurnNumber(pub u64);
+
+/// The player whose view the UI shows.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LocalPlayer(pub Entity);
+
+/// Set when the player ends the turn, so the next update runs the simulation once.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EndTurn(pub bool);
+
+/// The seed every random choice made while generating the galaxy derives from.
+/// Taken from `Config::seed` when set, otherwise picked at random and logged so a
+/// run can be replayed.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GalaxySeed(pub u64);
+
+impl FromWorld for GalaxySeed {
+    fn from_world(world: &mut World) -> Self {
+        let seed = match world
+            .get_resource::<Config>()
+            .and_then(|config| config.seed)
+        {
+            Some(seed) => seed,
+            None => thread_rng().gen(),
+        };
+        log::info!("galaxy seed: {}", seed);
+        GalaxySeed(seed)
+    }
+}
+
+/// The single RNG used by galaxy generation. ChaCha is used rather than `StdRng`
+/// because its output is stable across rand releases.
 #[derive(Resource)]
+pub struct GalaxyRng(pub ChaCha8Rng);
+
+impl FromWorld for GalaxyRng {
+    fn from_world(world: &mut World) -> Self {
+        world.init_resource::<GalaxySeed>();
+        let seed = world.resource::<GalaxySeed>().0;
+        GalaxyRng(ChaCha8Rng::seed_from_u64(seed))
+    }
+}
+
+#[derive(Resource, Clone, Serialize, Deserialize)]
 pub struct NameGenerator {
+    #[serde(skip)]
     names: Vec<String>,
     used_names: Vec<String>,
 }
 
 impl NameGenerator {
     pub fn new() -> Self {
-        let mut name_generator = NameGenerator {
-            names: Vec::new(),
-            used_names: Vec::new(),
-        };
+        let mut name_generator = NameGenerator::from_names(Vec::new());
         name_generator.load_names();
         name_generator
     }
-    fn load_names(&mut self) {
+
+    pub fn from_names(names: Vec<String>) -> Self {
+        NameGenerator {
+            names,
+            used_names: Vec::new(),
+        }
+    }
+
+    /// Take over the names already handed out by a generator restored from a save,
+    /// keeping the name list loaded by this one.
+    pub fn restore_used_names(&mut self, saved: NameGenerator) {
+        self.used_names = saved.used_names;
+    }
+
+    pub fn load_names(&mut self) {
         log::info!("loading names");
         let contents: String = fs::read_to_string("names.csv").expect("unable to read file");
         self.names = contents.split("\n").map(|s| s.to_string()).collect();
         log::info!("loaded {} names", self.names.len());
     }
 
-    pub fn random_name(&mut self) -> String {
-        let strategy = random::<u8>() % 3;
+    pub fn random_name<R: Rng>(&mut self, rng: &mut R) -> String {
+        let strategy = rng.gen_range(0..3);
         let mut name_used = true;
         let mut name = String::new();
         while name_used {
             name = match strategy {
-                0 => Self::random_name_default(&self.names),
-                1 => Self::random_name_mixed(&self.names),
-                2 => Self::random_name_mixed_with_num(&self.names),
-                3 => Self::random_name_default_with_num(&self.names),
+                0 => Self::random_name_default(&self.names, rng),
+                1 => Self::random_name_mixed(&self.names, rng),
+                2 => Self::random_name_mixed_with_num(&self.names, rng),
+                3 => Self::random_name_default_with_num(&self.names, rng),
                 _ => panic!("Invalid strategy"),
             };
             if !self.used_names.contains(&name) {
@@ -74,23 +385,23 @@ impl NameGenerator {
         name
     }
 
-    fn random_name_default(names: &Vec<String>) -> String {
-        let index = random::<usize>() % names.len();
+    fn random_name_default<R: Rng>(names: &[String], rng: &mut R) -> String {
+        let index = rng.gen_range(0..names.len());
         names[index].clone()
     }
 
-    fn random_name_default_with_num(names: &Vec<String>) -> String {
-        let index = random::<usize>() % names.len();
+    fn random_name_default_with_num<R: Rng>(names: &[String], rng: &mut R) -> String {
+        let index = rng.gen_range(0..names.len());
         let name = names[index].clone();
-        let number: u32 = random::<u32>() % 999;
+        let number: u32 = rng.gen_range(0..999);
         let number_chars: Vec<char> = number.to_string().chars().collect::<Vec<_>>();
         name.chars().chain(number_chars).collect::<String>()
     }
 
-    fn random_name_mixed(names: &Vec<String>) -> String {
-        let index1 = random::<usize>() % names.len();
+    fn random_name_mixed<R: Rng>(names: &[String], rng: &mut R) -> String {
+        let index1 = rng.gen_range(0..names.len());
         let name1: Vec<char> = names[index1].clone().chars().collect::<Vec<_>>();
-        let index2 = random::<usize>() % names.len();
+        let index2 = rng.gen_range(0..names.len());
         let name2: Vec<char> = names[index2].clone().chars().collect::<Vec<_>>();
         name1[..name1.len() / 2]
             .iter()
@@ -98,17 +409,17 @@ impl NameGenerator {
             .collect::<String>()
     }
 
-    fn random_name_mixed_with_num(names: &Vec<String>) -> String {
-        let index1 = random::<usize>() % names.len();
+    fn random_name_mixed_with_num<R: Rng>(names: &[String], rng: &mut R) -> String {
+        let index1 = rng.gen_range(0..names.len());
         let name1: Vec<char> = names[index1].clone().chars().collect::<Vec<_>>();
-        let index2 = random::<usize>() % names.len();
+        let index2 = rng.gen_range(0..names.len());
         let name2: Vec<char> = names[index2].clone().chars().collect::<Vec<_>>();
-        let number: u32 = random::<u32>() % 999;
+        let number: u32 = rng.gen_range(0..999);
         let number_chars: Vec<char> = number.to_string().chars().collect::<Vec<_>>();
         name1[..name1.len() / 2]
             .iter()
             .chain(name2[name2.len() / 2..].iter())
-            .chain(vec!['-'].iter())
+            .chain(['-'].iter())
             .chain(number_chars.iter())
             .collect::<String>()
     }
@@ -0,0 +1,853 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::{ecs::world::EntityRef, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    combat,
+    components::{self as cmp, ship, structure},
+    fog, resources, ships,
+};
+
+/// Bump this whenever the layout of `SaveGame` changes in a way older saves can't be read.
+/// 2: cargo bays hold cargo by kind instead of a single amount.
+pub const SAVE_VERSION: u32 = 2;
+pub const SAVE_DIR: &str = "saves";
+pub const AUTOSAVE_FILE: &str = "autosave.ron";
+pub const QUICKSAVE_FILE: &str = "quicksave.ron";
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Serialize(ron::Error),
+    Deserialize(ron::error::SpannedError),
+    Version { found: u32, expected: u32 },
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "save file io error: {}", err),
+            SaveError::Serialize(err) => write!(f, "failed to serialize save: {}", err),
+            SaveError::Deserialize(err) => write!(f, "failed to parse save: {}", err),
+            SaveError::Version { found, expected } => write!(
+                f,
+                "save version {} is not supported (expected {})",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(err: io::Error) -> Self {
+        SaveError::Io(err)
+    }
+}
+
+impl From<ron::Error> for SaveError {
+    fn from(err: ron::Error) -> Self {
+        SaveError::Serialize(err)
+    }
+}
+
+impl From<ron::error::SpannedError> for SaveError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        SaveError::Deserialize(err)
+    }
+}
+
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    pub config: resources::Config,
+    pub name_generator: resources::NameGenerator,
+    #[serde(default)]
+    pub turn: u64,
+    /// The `id` of the player at the keyboard.
+    #[serde(default)]
+    pub local_player: Option<u32>,
+    /// The cells each player, by `id`, has explored.
+    #[serde(default)]
+    pub explored: Vec<(u32, Vec<(u32, u32)>)>,
+    pub entities: Vec<SavedEntity>,
+}
+
+/// One entity and every component we know how to persist. All components are optional
+/// so new ones can be added without touching the entities that don't carry them.
+#[derive(Serialize, Deserialize, Default)]
+pub struct SavedEntity {
+    pub id: u32,
+    /// The `id` of this entity's parent in the save, e.g. the star a planet orbits.
+    #[serde(default)]
+    pub parent: Option<u32>,
+    #[serde(default)]
+    pub location: Option<cmp::Location>,
+    #[serde(default)]
+    pub name: Option<cmp::Name>,
+    #[serde(default)]
+    pub galactic_obj: Option<cmp::astronomy::GalacticObj>,
+    #[serde(default)]
+    pub stellar_obj: Option<cmp::astronomy::StellarObj>,
+    #[serde(default)]
+    pub planet_class: Option<cmp::astronomy::PlanetClass>,
+    #[serde(default)]
+    pub body: Option<cmp::astronomy::Body>,
+    #[serde(default)]
+    pub player: Option<SavedPlayer>,
+    /// The `id` of the player owning this entity.
+    #[serde(default)]
+    pub owner: Option<u32>,
+    #[serde(default)]
+    pub visibility: Option<cmp::Visibility>,
+    #[serde(default)]
+    pub structure: Option<cmp::Structure>,
+    #[serde(default)]
+    pub size: Option<structure::Size>,
+    #[serde(default)]
+    pub construction: Option<structure::ConstructionQueue>,
+    #[serde(default)]
+    pub pipeline: Option<SavedPipelineLink>,
+    #[serde(default)]
+    pub engine: Option<ship::Engine>,
+    #[serde(default)]
+    pub cargo_bay: Option<ship::CargoBay>,
+    #[serde(default)]
+    pub defense_system: Option<ship::DefenseSystem>,
+    /// The `id` of the star a ship is flying to. The path is plotted again on load.
+    #[serde(default)]
+    pub destination: Option<u32>,
+    #[serde(default)]
+    pub hull: Option<ship::Hull>,
+    #[serde(default)]
+    pub shields: Option<ship::Shields>,
+    #[serde(default)]
+    pub disabled: Option<ship::Disabled>,
+    #[serde(default)]
+    pub stockpile: Option<cmp::Stockpile>,
+    #[serde(default)]
+    pub trade_route: Option<SavedTradeRoute>,
+}
+
+/// `cmp::Player` with its homeworld as an `id` in the save.
+#[derive(Serialize, Deserialize)]
+pub struct SavedPlayer {
+    pub name: String,
+    pub color: cmp::PlayerColor,
+    pub homeworld: Option<u32>,
+}
+
+/// `structure::PipelineLink` with the planet it pumps to as an `id` in the save. Its flow
+/// is worked out again on the next tick.
+#[derive(Serialize, Deserialize)]
+pub struct SavedPipelineLink {
+    pub to: u32,
+    pub capacity: u32,
+}
+
+/// `ship::TradeRoute` with its stops' planets as `id`s in the save.
+#[derive(Serialize, Deserialize)]
+pub struct SavedTradeRoute {
+    pub stops: Vec<SavedTradeStop>,
+    pub next: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedTradeStop {
+    pub planet: u32,
+    pub unload: Vec<cmp::Cargo>,
+    pub load: Vec<cmp::Cargo>,
+}
+
+impl SavedEntity {
+    fn from_entity(entity: EntityRef) -> SavedEntity {
+        SavedEntity {
+            id: entity.id().index(),
+            parent: entity.get::<Parent>().map(|parent| parent.get().index()),
+            location: entity.get::<cmp::Location>().cloned(),
+            name: entity.get::<cmp::Name>().cloned(),
+            galactic_obj: entity.get::<cmp::astronomy::GalacticObj>().copied(),
+            stellar_obj: entity.get::<cmp::astronomy::StellarObj>().copied(),
+            planet_class: entity.get::<cmp::astronomy::PlanetClass>().copied(),
+            body: entity.get::<cmp::astronomy::Body>().copied(),
+            player: entity.get::<cmp::Player>().map(|player| SavedPlayer {
+                name: player.name.clone(),
+                color: player.color,
+                homeworld: player.homeworld.map(|homeworld| homeworld.index()),
+            }),
+            owner: entity.get::<cmp::Owner>().map(|owner| owner.0.index()),
+            visibility: entity.get::<cmp::Visibility>().copied(),
+            structure: entity.get::<cmp::Structure>().copied(),
+            size: entity.get::<structure::Size>().copied(),
+            construction: entity.get::<structure::ConstructionQueue>().cloned(),
+            pipeline: entity
+                .get::<structure::PipelineLink>()
+                .map(|link| SavedPipelineLink {
+                    to: link.to.index(),
+                    capacity: link.capacity,
+                }),
+            engine: entity.get::<ship::Engine>().cloned(),
+            cargo_bay: entity.get::<ship::CargoBay>().cloned(),
+            defense_system: entity.get::<ship::DefenseSystem>().copied(),
+            destination: entity
+                .get::<ship::Course>()
+                .map(|course| course.destination.index()),
+            hull: entity.get::<ship::Hull>().copied(),
+            shields: entity.get::<ship::Shields>().copied(),
+            disabled: entity.get::<ship::Disabled>().copied(),
+            stockpile: entity.get::<cmp::Stockpile>().cloned(),
+            trade_route: entity
+                .get::<ship::TradeRoute>()
+                .map(|route| SavedTradeRoute {
+                    stops: route
+                        .stops
+                        .iter()
+                        .map(|stop| SavedTradeStop {
+                            planet: stop.planet.index(),
+                            unload: stop.unload.clone(),
+                            load: stop.load.clone(),
+                        })
+                        .collect(),
+                    next: route.next,
+                }),
+        }
+    }
+
+    fn has_components(&self) -> bool {
+        self.location.is_some()
+            || self.name.is_some()
+            || self.galactic_obj.is_some()
+            || self.stellar_obj.is_some()
+            || self.planet_class.is_some()
+            || self.body.is_some()
+            || self.player.is_some()
+            || self.visibility.is_some()
+            || self.structure.is_some()
+            || self.construction.is_some()
+            || self.engine.is_some()
+            || self.cargo_bay.is_some()
+            || self.defense_system.is_some()
+            || self.hull.is_some()
+            || self.shields.is_some()
+            || self.stockpile.is_some()
+    }
+
+    fn spawn(mut self, world: &mut World) -> Entity {
+        // ships saved before they could fight get a scout's hull and shields, or they'd
+        // never be drawn into a battle
+        if self.engine.is_some() {
+            let scout = ships::scout((0, 0));
+            self.hull.get_or_insert(scout.hull);
+            self.shields.get_or_insert(scout.shields);
+        }
+        let mut entity = world.spawn_empty();
+        if let Some(location) = self.location {
+            entity.insert(location);
+        }
+        if let Some(name) = self.name {
+            entity.insert(name);
+        }
+        if let Some(galactic_obj) = self.galactic_obj {
+            entity.insert(galactic_obj);
+        }
+        if let Some(stellar_obj) = self.stellar_obj {
+            entity.insert(stellar_obj);
+        }
+        if let Some(planet_class) = self.planet_class {
+            entity.insert(planet_class);
+        }
+        if let Some(body) = self.body {
+            entity.insert(body);
+        }
+        if let Some(visibility) = self.visibility {
+            entity.insert(visibility);
+        }
+        if let Some(structure) = self.structure {
+            entity.insert(structure);
+        }
+        if let Some(size) = self.size {
+            entity.insert(size);
+        }
+        if let Some(construction) = self.construction {
+            entity.insert(construction);
+        }
+        if let Some(engine) = self.engine {
+            entity.insert(engine);
+        }
+        if let Some(cargo_bay) = self.cargo_bay {
+            entity.insert(cargo_bay);
+        }
+        if let Some(defense_system) = self.defense_system {
+            entity.insert(defense_system);
+        }
+        if let Some(hull) = self.hull {
+            entity.insert(hull);
+        }
+        if let Some(shields) = self.shields {
+            entity.insert(shields);
+        }
+        if let Some(disabled) = self.disabled {
+            entity.insert(disabled);
+        }
+        if let Some(stockpile) = self.stockpile {
+            entity.insert(stockpile);
+        }
+        if let Some(player) = self.player {
+            // the homeworld is filled in once every entity has been spawned
+            entity.insert(cmp::Player {
+                name: player.name,
+                color: player.color,
+                homeworld: None,
+            });
+        }
+        entity.id()
+    }
+}
+
+impl SaveGame {
+    pub fn from_world(world: &mut World) -> SaveGame {
+        let entities = world
+            .iter_entities()
+            .map(|entity| SavedEntity::from_entity(world.entity(entity)))
+            .filter(|saved| saved.has_components())
+            .collect();
+        SaveGame {
+            version: SAVE_VERSION,
+            config: world.resource::<resources::Config>().clone(),
+            name_generator: world.resource::<resources::NameGenerator>().clone(),
+            turn: world
+                .get_resource::<resources::TurnNumber>()
+                .map_or(0, |turn| turn.0),
+            local_player: world
+                .get_resource::<resources::LocalPlayer>()
+                .map(|player| player.0.index()),
+            explored: world
+                .get_resource::<fog::FogOfWar>()
+                .map(|fog| {
+                    fog.players
+                        .iter()
+                        .map(|(player, player_fog)| {
+                            let mut cells: Vec<(u32, u32)> =
+                                player_fog.explored.iter().copied().collect();
+                            cells.sort();
+                            (player.index(), cells)
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            entities,
+        }
+    }
+
+    /// Replace every entity and saved resource in `world` with the contents of this save.
+    pub fn apply(self, world: &mut World) {
+        world.clear_entities();
+        world.insert_resource(self.config);
+        world.insert_resource(resources::TurnNumber(self.turn));
+        match world.get_resource_mut::<resources::NameGenerator>() {
+            Some(mut name_generator) => name_generator.restore_used_names(self.name_generator),
+            None => {
+                let mut name_generator = self.name_generator;
+                name_generator.load_names();
+                world.insert_resource(name_generator);
+            }
+        }
+
+        // entity ids are only meaningful within the save, so map them to the new entities
+        // before restoring any relationships between them
+        let mut spawned = HashMap::new();
+        let mut parents = Vec::new();
+        let mut owners = Vec::new();
+        let mut homeworlds = Vec::new();
+        let mut courses = Vec::new();
+        let mut trade_routes = Vec::new();
+        let mut pipelines = Vec::new();
+        for mut saved in self.entities {
+            let id = saved.id;
+            if let Some(parent) = saved.parent {
+                parents.push((id, parent));
+            }
+            if let Some(owner) = saved.owner {
+                owners.push((id, owner));
+            }
+            if let Some(homeworld) = saved.player.as_ref().and_then(|player| player.homeworld) {
+                homeworlds.push((id, homeworld));
+            }
+            if let Some(destination) = saved.destination {
+                courses.push((id, destination));
+            }
+            if let Some(route) = saved.trade_route.take() {
+                trade_routes.push((id, route));
+            }
+            if let Some(link) = saved.pipeline.take() {
+                pipelines.push((id, link));
+            }
+            spawned.insert(id, saved.spawn(world));
+        }
+        for (owned, owner) in owners {
+            match (spawned.get(&owned), spawned.get(&owner)) {
+                (Some(&owned), Some(&owner)) => {
+                    world.entity_mut(owned).insert(cmp::Owner(owner));
+                }
+                _ => log::warn!("save references missing owner {} of {}", owner, owned),
+            }
+        }
+        for (player, homeworld) in homeworlds {
+            if let (Some(&player), Some(&homeworld)) =
+                (spawned.get(&player), spawned.get(&homeworld))
+            {
+                if let Some(mut player) = world.get_mut::<cmp::Player>(player) {
+                    player.homeworld = Some(homeworld);
+                }
+            }
+        }
+        for (ship, destination) in courses {
+            match (spawned.get(&ship), spawned.get(&destination)) {
+                (Some(&ship), Some(&destination)) => {
+                    if let Err(err) = ships::set_course(world, ship, destination) {
+                        log::warn!("can't restore the course of ship {}: {}", ship.index(), err);
+                    }
+                }
+                _ => log::warn!(
+                    "save references missing destination {} of {}",
+                    destination,
+                    ship
+                ),
+            }
+        }
+        for (ship, route) in trade_routes {
+            let stops: Option<Vec<ship::TradeStop>> = route
+                .stops
+                .into_iter()
+                .map(|stop| {
+                    Some(ship::TradeStop {
+                        planet: *spawned.get(&stop.planet)?,
+                        unload: stop.unload,
+                        load: stop.load,
+                    })
+                })
+                .collect();
+            match (spawned.get(&ship), stops) {
+                (Some(&ship), Some(stops)) => {
+                    world.entity_mut(ship).insert(ship::TradeRoute {
+                        stops,
+                        next: route.next,
+                    });
+                }
+                _ => log::warn!("save references missing trade route stops of {}", ship),
+            }
+        }
+        for (pipeline, link) in pipelines {
+            match (spawned.get(&pipeline), spawned.get(&link.to)) {
+                (Some(&pipeline), Some(&to)) => {
+                    world.entity_mut(pipeline).insert(structure::PipelineLink {
+                        to,
+                        capacity: link.capacity,
+                        flow: 0,
+                        disrupted: false,
+                    });
+                }
+                _ => log::warn!(
+                    "save references missing end {} of pipeline {}",
+                    link.to,
+                    pipeline
+                ),
+            }
+        }
+        match self.local_player.and_then(|id| spawned.get(&id)) {
+            Some(&player) => world.insert_resource(resources::LocalPlayer(player)),
+            None => {
+                world.remove_resource::<resources::LocalPlayer>();
+            }
+        }
+        // what's visible right now is worked out again on the next update
+        let mut fog_of_war = fog::FogOfWar::default();
+        for (id, cells) in self.explored {
+            if let Some(&player) = spawned.get(&id) {
+                let player_fog = fog_of_war.players.entry(player).or_default();
+                player_fog.explored.extend(cells);
+            }
+        }
+        world.insert_resource(fog_of_war);
+        // the reports point at the entities of the game being replaced
+        world.insert_resource(combat::CombatLog::default());
+        for (child, parent) in parents {
+            match (spawned.get(&child), spawned.get(&parent)) {
+                (Some(&child), Some(&parent)) => {
+                    world.entity_mut(parent).push_children(&[child]);
+                }
+                _ => log::warn!("save references missing parent {} of {}", parent, child),
+            }
+        }
+        log::info!("restored {} entities", spawned.len());
+    }
+
+    pub fn to_ron(&self) -> Result<String, SaveError> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    pub fn from_ron(contents: &str) -> Result<SaveGame, SaveError> {
+        let header: SaveHeader = ron::from_str(contents)?;
+        if header.version != SAVE_VERSION {
+            return Err(SaveError::Version {
+                found: header.version,
+                expected: SAVE_VERSION,
+            });
+        }
+        Ok(ron::from_str(contents)?)
+    }
+}
+
+pub fn save_path(file: &str) -> PathBuf {
+    Path::new(SAVE_DIR).join(file)
+}
+
+/// The most recently written file in the save directory, autosave or otherwise.
+pub fn latest_save() -> Option<PathBuf> {
+    fs::read_dir(SAVE_DIR)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "ron"))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+pub fn save_game(world: &mut World, path: &Path) -> Result<(), SaveError> {
+    log::info!("saving game to {}", path.display());
+    let contents = SaveGame::from_world(world).to_ron()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+pub fn load_game(world: &mut World, path: &Path) -> Result<(), SaveError> {
+    log::info!("loading game from {}", path.display());
+    let contents = fs::read_to_string(path)?;
+    SaveGame::from_ron(&contents)?.apply(world);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    fn world_with_stars() -> World {
+        let mut world = World::new();
+        world.insert_resource(resources::Config {
+            galaxy_dimension: 10,
+            num_stars: 2,
+            ..Default::default()
+        });
+        world.insert_resource(resources::NameGenerator::from_names(vec![
+            "Sol".to_string(),
+            "Vega".to_string(),
+        ]));
+        for (x, name) in [(1, "Sol"), (4, "Vega")] {
+            world.spawn((
+                cmp::Location {
+                    x,
+                    y: 2,
+                    z: 0,
+                    w: 0,
+                    ui_offset: (0.25, -0.5),
+                },
+                cmp::astronomy::GalacticObj::Star,
+                cmp::Name(name.to_string()),
+            ));
+        }
+        world
+    }
+
+    #[test]
+    fn test_save_round_trip() {
+        let mut world = world_with_stars();
+        world.insert_resource(resources::TurnNumber(7));
+        let contents = SaveGame::from_world(&mut world).to_ron().unwrap();
+
+        let mut loaded = World::new();
+        loaded.insert_resource(resources::NameGenerator::from_names(Vec::new()));
+        SaveGame::from_ron(&contents).unwrap().apply(&mut loaded);
+
+        let mut query =
+            loaded.query::<(&cmp::Name, &cmp::Location, &cmp::astronomy::GalacticObj)>();
+        let mut stars: Vec<(String, u32, u32)> = query
+            .iter(&loaded)
+            .map(|(name, loc, _)| (name.0.clone(), loc.x, loc.y))
+            .collect();
+        stars.sort();
+        assert_eq!(
+            stars,
+            vec![("Sol".to_string(), 1, 2), ("Vega".to_string(), 4, 2)]
+        );
+        assert_eq!(loaded.resource::<resources::Config>().galaxy_dimension, 10);
+        assert_eq!(loaded.resource::<resources::TurnNumber>().0, 7);
+    }
+
+    #[test]
+    fn test_save_keeps_hierarchy() {
+        let mut world = world_with_stars();
+        let star = world
+            .query_filtered::<Entity, With<cmp::astronomy::GalacticObj>>()
+            .iter(&world)
+            .next()
+            .unwrap();
+        let star_name = world.get::<cmp::Name>(star).unwrap().0.clone();
+        let planet = world
+            .spawn((
+                cmp::astronomy::StellarObj::Planet,
+                cmp::astronomy::PlanetClass::Ocean,
+                cmp::Name("Sol I".to_string()),
+            ))
+            .id();
+        world.spawn((
+            cmp::astronomy::StellarObj::Moon,
+            cmp::Name("Sol I b".to_string()),
+        ));
+        let moon = world
+            .query_filtered::<Entity, With<cmp::astronomy::StellarObj>>()
+            .iter(&world)
+            .find(|&entity| entity != planet)
+            .unwrap();
+        world.entity_mut(star).push_children(&[planet]);
+        world.entity_mut(planet).push_children(&[moon]);
+        let contents = SaveGame::from_world(&mut world).to_ron().unwrap();
+
+        let mut loaded = World::new();
+        loaded.insert_resource(resources::NameGenerator::from_names(Vec::new()));
+        SaveGame::from_ron(&contents).unwrap().apply(&mut loaded);
+
+        let mut query = loaded.query::<(&cmp::Name, &Parent)>();
+        let mut parents: Vec<(String, String)> = query
+            .iter(&loaded)
+            .map(|(name, parent)| {
+                let parent_name = loaded.get::<cmp::Name>(parent.get()).unwrap();
+                (name.0.clone(), parent_name.0.clone())
+            })
+            .collect();
+        parents.sort();
+        assert_eq!(
+            parents,
+            vec![
+                ("Sol I".to_string(), star_name),
+                ("Sol I b".to_string(), "Sol I".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_save_keeps_players_and_owners() {
+        let mut world = world_with_stars();
+        let star = world
+            .query_filtered::<Entity, With<cmp::astronomy::GalacticObj>>()
+            .iter(&world)
+            .next()
+            .unwrap();
+        let player = world
+            .spawn(cmp::Player {
+                name: "Player 1".to_string(),
+                color: cmp::PlayerColor::Green,
+                homeworld: Some(star),
+            })
+            .id();
+        world
+            .entity_mut(star)
+            .insert((cmp::Owner(player), cmp::Visibility { range: 3 }));
+        world.insert_resource(resources::LocalPlayer(player));
+        let mut fog_of_war = fog::FogOfWar::default();
+        fog_of_war
+            .players
+            .entry(player)
+            .or_default()
+            .explored
+            .extend([(1, 2), (2, 2)]);
+        world.insert_resource(fog_of_war);
+        let contents = SaveGame::from_world(&mut world).to_ron().unwrap();
+
+        let mut loaded = World::new();
+        loaded.insert_resource(resources::NameGenerator::from_names(Vec::new()));
+        SaveGame::from_ron(&contents).unwrap().apply(&mut loaded);
+
+        let (player, homeworld) = loaded
+            .query::<(Entity, &cmp::Player)>()
+            .iter(&loaded)
+            .map(|(entity, player)| (entity, player.homeworld.unwrap()))
+            .next()
+            .unwrap();
+        assert_eq!(
+            loaded.get::<cmp::Owner>(homeworld),
+            Some(&cmp::Owner(player))
+        );
+        assert_eq!(loaded.query::<&cmp::Owner>().iter(&loaded).count(), 1);
+        assert_eq!(
+            loaded.get::<cmp::Visibility>(homeworld),
+            Some(&cmp::Visibility { range: 3 })
+        );
+        assert_eq!(loaded.resource::<resources::LocalPlayer>().0, player);
+        let explored = &loaded.resource::<fog::FogOfWar>().players[&player].explored;
+        assert!(explored.contains(&(1, 2)) && explored.contains(&(2, 2)));
+    }
+
+    #[test]
+    fn test_save_keeps_ships_courses_and_structures() {
+        let mut world = world_with_stars();
+        let vega = world
+            .query::<(Entity, &cmp::Name)>()
+            .iter(&world)
+            .find(|(_, name)| name.0 == "Vega")
+            .map(|(entity, _)| entity)
+            .unwrap();
+        let ship = world.spawn(ships::scout((1, 2))).id();
+        assert!(ships::set_course(&mut world, ship, vega).is_ok());
+        world.get_mut::<ship::Hull>(ship).unwrap().current = 7;
+        world.spawn((ships::scout((4, 2)), ship::Disabled));
+        world.entity_mut(vega).with_children(|planet| {
+            planet.spawn((cmp::Structure::SpacePort, structure::Size(2)));
+        });
+        world.entity_mut(vega).insert(structure::ConstructionQueue(
+            [structure::Order {
+                structure: cmp::Structure::Radar,
+                remaining: 3,
+            }]
+            .into(),
+        ));
+        let sol = world
+            .query::<(Entity, &cmp::Name)>()
+            .iter(&world)
+            .find(|(_, name)| name.0 == "Sol")
+            .map(|(entity, _)| entity)
+            .unwrap();
+        world.entity_mut(sol).with_children(|planet| {
+            planet.spawn((
+                cmp::Structure::Pipeline,
+                structure::PipelineLink {
+                    to: vega,
+                    capacity: 10,
+                    flow: 4,
+                    disrupted: false,
+                },
+            ));
+        });
+        world.entity_mut(sol).insert(cmp::Stockpile(
+            [(cmp::Cargo::Probes, 4)].into_iter().collect(),
+        ));
+        let stop = |planet| ship::TradeStop {
+            planet,
+            unload: vec![cmp::Cargo::Probes],
+            load: vec![],
+        };
+        world.entity_mut(ship).insert(ship::TradeRoute {
+            stops: vec![stop(sol), stop(vega)],
+            next: 1,
+        });
+        let contents = SaveGame::from_world(&mut world).to_ron().unwrap();
+
+        let mut loaded = World::new();
+        loaded.insert_resource(resources::NameGenerator::from_names(Vec::new()));
+        SaveGame::from_ron(&contents).unwrap().apply(&mut loaded);
+
+        let (engine, hull, course) = loaded
+            .query::<(&ship::Engine, &ship::Hull, &ship::Course)>()
+            .single(&loaded);
+        assert_eq!(engine, &ships::scout((0, 0)).engine);
+        assert_eq!(hull.current, 7);
+        assert_eq!(
+            loaded.get::<cmp::Name>(course.destination).unwrap().0,
+            "Vega"
+        );
+        assert_eq!(Vec::from(course.path.clone()), vec![(2, 2), (3, 2), (4, 2)]);
+        let (structure, parent) = loaded
+            .query_filtered::<(&cmp::Structure, &Parent), Without<structure::PipelineLink>>()
+            .single(&loaded);
+        assert_eq!(*structure, cmp::Structure::SpacePort);
+        assert_eq!(loaded.get::<cmp::Name>(parent.get()).unwrap().0, "Vega");
+        let queue = loaded
+            .get::<structure::ConstructionQueue>(parent.get())
+            .unwrap();
+        assert_eq!(queue.0[0].structure, cmp::Structure::Radar);
+        assert_eq!(queue.0[0].remaining, 3);
+        let link = loaded.query::<&structure::PipelineLink>().single(&loaded);
+        assert_eq!((link.capacity, link.flow), (10, 0));
+        assert_eq!(loaded.get::<cmp::Name>(link.to).unwrap().0, "Vega");
+        let (stockpile, name) = loaded
+            .query::<(&cmp::Stockpile, &cmp::Name)>()
+            .single(&loaded);
+        assert_eq!(name.0, "Sol");
+        assert_eq!(stockpile.0[&cmp::Cargo::Probes], 4);
+        let route = loaded.query::<&ship::TradeRoute>().single(&loaded);
+        assert_eq!(route.next, 1);
+        assert_eq!(
+            loaded.get::<cmp::Name>(route.stops[1].planet).unwrap().0,
+            "Vega"
+        );
+        assert_eq!(loaded.query::<&ship::Disabled>().iter(&loaded).count(), 1);
+    }
+
+    #[test]
+    fn test_old_ships_get_hull_and_shields() {
+        let mut world = world_with_stars();
+        world.spawn(ships::scout((1, 2)));
+        let mut save = SaveGame::from_world(&mut world);
+        for saved in &mut save.entities {
+            saved.hull = None;
+            saved.shields = None;
+        }
+
+        let mut loaded = World::new();
+        loaded.insert_resource(resources::NameGenerator::from_names(Vec::new()));
+        save.apply(&mut loaded);
+        let (hull, shields) = loaded
+            .query::<(&ship::Hull, &ship::Shields)>()
+            .single(&loaded);
+        assert_eq!(*hull, ships::scout((0, 0)).hull);
+        assert_eq!(*shields, ships::scout((0, 0)).shields);
+    }
+
+    #[test]
+    fn test_load_replaces_existing_entities() {
+        let mut world = world_with_stars();
+        let save = SaveGame::from_world(&mut world);
+        world.spawn(cmp::Name("Stray".to_string()));
+        world.insert_resource(combat::CombatLog {
+            reports: vec![combat::CombatReport {
+                cell: (1, 2),
+                tick: 3,
+                turn: 0,
+                rounds: 1,
+                ships: Vec::new(),
+            }],
+        });
+
+        save.apply(&mut world);
+        assert_eq!(world.query::<&cmp::Name>().iter(&world).count(), 2);
+        assert!(world.resource::<combat::CombatLog>().reports.is_empty());
+    }
+
+    #[test]
+    fn test_rejects_other_versions() {
+        let mut world = world_with_stars();
+        let mut save = SaveGame::from_world(&mut world);
+        save.version = SAVE_VERSION + 1;
+        let contents = save.to_ron().unwrap();
+        assert!(matches!(
+            SaveGame::from_ron(&contents),
+            Err(SaveError::Version { .. })
+        ));
+    }
+}
@@ -0,0 +1,167 @@
+use bevy::prelude::{App, Entity};
+
+use crate::{
+    components::{self as cmp, ship},
+    fog, resources,
+};
+
+/// Search results are listed up to this many matches, more don't fit on screen anyway.
+pub const MAX_RESULTS: usize = 50;
+
+/// A named object matching the search query, with the galaxy cell it lives in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub entity: Entity,
+    pub name: String,
+    pub cell: (u32, u32),
+    pub score: i64,
+}
+
+/// Score how well `query` fuzzy-matches `name`, or `None` if the characters of the query
+/// don't all appear in the name in order. Matching is case-insensitive; consecutive
+/// characters and characters starting a word score higher, skipped characters lower.
+pub fn fuzzy_score(query: &str, name: &str) -> Option<i64> {
+    let mut query_chars = query.chars().flat_map(char::to_lowercase).peekable();
+    let mut score = 0;
+    let mut prev_matched = false;
+    let mut prev_char: Option<char> = None;
+    for c in name.chars() {
+        let Some(&q) = query_chars.peek() else {
+            break;
+        };
+        let word_start = !prev_char.is_some_and(|p| p.is_alphanumeric());
+        if c.to_lowercase().eq(std::iter::once(q)) {
+            score += 1;
+            if prev_matched {
+                score += 5;
+            }
+            if word_start {
+                score += 8;
+            }
+            prev_matched = true;
+            query_chars.next();
+        } else {
+            score -= 1;
+            prev_matched = false;
+        }
+        prev_char = Some(c);
+    }
+    query_chars.peek().is_none().then_some(score)
+}
+
+/// Every named object whose name matches `query`, best match first. Planets and moons
+/// report the cell of the star they orbit. Objects hidden by fog of war aren't found, and
+/// other players' ships only within sensor range, as on the galaxy map.
+pub fn search_objs(app: &mut App, query: &str) -> Vec<SearchResult> {
+    let mut named = app.world.query::<(
+        Entity,
+        &cmp::Name,
+        &cmp::Location,
+        Option<&cmp::Owner>,
+        Option<&ship::Engine>,
+    )>();
+    let local_player = app
+        .world
+        .get_resource::<resources::LocalPlayer>()
+        .map(|player| player.0);
+    let fog = fog::local_fog(&app.world);
+    let mut results: Vec<SearchResult> = named
+        .iter(&app.world)
+        .filter(|(_, _, loc, owner, engine)| {
+            let Some(fog) = fog else {
+                return true;
+            };
+            let cell = (loc.x, loc.y);
+            let foreign_ship = engine.is_some() && owner.map(|owner| owner.0) != local_player;
+            if foreign_ship {
+                fog.visible.contains(&cell)
+            } else {
+                fog.explored.contains(&cell)
+            }
+        })
+        .filter_map(|(entity, name, loc, _, _)| {
+            Some(SearchResult {
+                entity,
+                name: name.0.clone(),
+                cell: (loc.x, loc.y),
+                score: fuzzy_score(query, &name.0)?,
+            })
+        })
+        .collect();
+    results.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then(a.name.len().cmp(&b.name.len()))
+            .then(a.name.cmp(&b.name))
+    });
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert!(fuzzy_score("sol", "Sol").is_some());
+        assert!(fuzzy_score("sl", "Sol I").is_some());
+        assert_eq!(fuzzy_score("los", "Sol"), None);
+        assert_eq!(fuzzy_score("solx", "Sol"), None);
+        // the empty query matches everything
+        assert!(fuzzy_score("", "Vega").is_some());
+        // consecutive and word-start matches beat scattered ones
+        assert!(fuzzy_score("veg", "Vega") > fuzzy_score("veg", "Vesta Gamma"));
+        assert!(fuzzy_score("sb", "Sol I b") > fuzzy_score("sb", "Sabik"));
+    }
+
+    #[test]
+    fn test_search_objs_ranking() {
+        let mut app = App::new();
+        for (name, x) in [("Vesta Gamma", 1), ("Vega", 2), ("Sol", 3), ("Vega I", 2)] {
+            app.world
+                .spawn((cmp::Name(name.to_string()), cmp::Location::at(x, 0)));
+        }
+        let results = search_objs(&mut app, "vega");
+        let names: Vec<&str> = results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["Vega", "Vega I", "Vesta Gamma"]);
+        assert_eq!(results[1].cell, (2, 0));
+    }
+
+    #[test]
+    fn test_search_hides_foreign_ships_out_of_sight() {
+        let mut app = App::new();
+        let player = app.world.spawn_empty().id();
+        let rival = app.world.spawn_empty().id();
+        app.insert_resource(resources::Config {
+            fog_of_war: true,
+            ..Default::default()
+        })
+        .insert_resource(resources::LocalPlayer(player));
+        let mut fog_of_war = fog::FogOfWar::default();
+        fog_of_war
+            .players
+            .entry(player)
+            .or_default()
+            .explored
+            .insert((5, 0));
+        app.insert_resource(fog_of_war);
+        for (name, owner) in [("Scout", player), ("Raider", rival)] {
+            app.world.spawn((
+                crate::ships::scout((5, 0)),
+                cmp::Name(name.to_string()),
+                cmp::Owner(owner),
+            ));
+        }
+        let names = |app: &mut App| -> Vec<String> {
+            search_objs(app, "").into_iter().map(|r| r.name).collect()
+        };
+        assert_eq!(names(&mut app), vec!["Scout"]);
+
+        let mut fog_of_war = app.world.resource_mut::<fog::FogOfWar>();
+        let player_fog = fog_of_war.players.get_mut(&player).unwrap();
+        player_fog.visible.insert((5, 0));
+        assert_eq!(names(&mut app), vec!["Scout", "Raider"]);
+    }
+}
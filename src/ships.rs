@@ -0,0 +1,488 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt,
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    combat,
+    components::{self as cmp, ship},
+};
+
+/// How far a ship's own sensors see.
+pub const SHIP_SENSOR_RANGE: u32 = 2;
+/// Fuel burnt crossing one cell with the least efficient engine. Better engines divide it
+/// by their `FuelEfficiency`.
+pub const FUEL_PER_CELL: u32 = 6;
+/// Fuel a ship takes on per tick while docked at one of its owner's space ports.
+pub const REFUEL_PER_TICK: u32 = 25;
+/// Hull a ship gets back per tick while docked at one of its owner's space ports.
+pub const REPAIR_PER_TICK: u32 = 2;
+
+pub fn fuel_per_cell(fuel_efficiency: ship::FuelEfficiency) -> u32 {
+    FUEL_PER_CELL / fuel_efficiency as u32
+}
+
+/// Fuel needed to cross `cells` cells.
+pub fn fuel_cost(fuel_efficiency: ship::FuelEfficiency, cells: u32) -> u32 {
+    fuel_per_cell(fuel_efficiency) * cells
+}
+
+/// How many cells the fuel left in `engine` takes the ship.
+pub fn range(engine: &ship::Engine) -> u32 {
+    engine.current_fuel / fuel_per_cell(engine.fuel_efficiency)
+}
+
+/// Cells a ship crosses getting from `a` to `b`: it moves diagonally as readily as
+/// straight, so this is the larger of the two offsets.
+pub fn distance(a: (u32, u32), b: (u32, u32)) -> u32 {
+    a.0.abs_diff(b.0).max(a.1.abs_diff(b.1))
+}
+
+/// The ship every player starts with, docked at `cell`.
+pub fn scout(cell: (u32, u32)) -> ship::ShipBundle {
+    ship::ShipBundle {
+        location: cmp::Location {
+            x: cell.0,
+            y: cell.1,
+            z: 0,
+            w: 0,
+            ui_offset: (0., 0.),
+        },
+        engine: ship::Engine {
+            current_fuel: 100,
+            max_fuel: 100,
+            fuel_efficiency: ship::FuelEfficiency::Fossil,
+            speed: 1,
+        },
+        cargo_bay: ship::CargoBay {
+            contents: Default::default(),
+            max_cargo: 50,
+        },
+        defense_system: ship::DefenseSystem::Laser(3),
+        hull: ship::Hull {
+            current: 20,
+            max: 20,
+        },
+        shields: ship::Shields {
+            current: 10,
+            max: 10,
+        },
+    }
+}
+
+/// Spawn a ship belonging to `owner`, with sensors of its own.
+pub fn spawn_ship(
+    commands: &mut Commands,
+    bundle: ship::ShipBundle,
+    name: String,
+    owner: Entity,
+) -> Entity {
+    commands
+        .spawn((
+            bundle,
+            cmp::Name(name),
+            cmp::Owner(owner),
+            cmp::Visibility {
+                range: SHIP_SENSOR_RANGE,
+            },
+        ))
+        .id()
+}
+
+/// The cells on the straight line from `from` to `to`, leaving out `from` itself. Each
+/// step moves at most one cell along either axis, so diagonals cost as much as straights.
+pub fn plot_course(from: (u32, u32), to: (u32, u32)) -> VecDeque<(u32, u32)> {
+    let (dx, dy) = (to.0 as f64 - from.0 as f64, to.1 as f64 - from.1 as f64);
+    let steps = dx.abs().max(dy.abs()) as u32;
+    (1..=steps)
+        .map(|step| {
+            let t = step as f64 / steps as f64;
+            (
+                (from.0 as f64 + dx * t).round() as u32,
+                (from.1 as f64 + dy * t).round() as u32,
+            )
+        })
+        .collect()
+}
+
+/// Why a ship can't be sent where it was ordered to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderError {
+    NoShip,
+    NoDestination,
+    OutOfRange {
+        needed: u32,
+        fuel: u32,
+    },
+    /// Cargo only moves between a ship and a planet in the same star system.
+    NotDocked,
+    NotOwned,
+    TooFewStops,
+    Disabled,
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OrderError::NoShip => write!(f, "no such ship"),
+            OrderError::NoDestination => write!(f, "no such destination"),
+            OrderError::OutOfRange { needed, fuel } => {
+                write!(f, "out of range: needs {} fuel, has {}", needed, fuel)
+            }
+            OrderError::NotDocked => write!(f, "the ship isn't at that planet"),
+            OrderError::NotOwned => write!(f, "that planet belongs to someone else"),
+            OrderError::TooFewStops => write!(f, "a trade route needs at least two stops"),
+            OrderError::Disabled => write!(f, "the ship is disabled"),
+        }
+    }
+}
+
+/// A course that a ship has enough fuel for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoursePlan {
+    pub path: VecDeque<(u32, u32)>,
+    pub fuel_cost: u32,
+    /// The ship would arrive without a space port to refuel at, and without the fuel left
+    /// to reach one.
+    pub strands: bool,
+}
+
+/// The cells of every space port `owner` has, where their ships refuel.
+pub fn space_port_cells(world: &mut World, owner: Entity) -> HashSet<(u32, u32)> {
+    let mut ports = world.query::<(&cmp::Structure, &cmp::Owner, &Parent)>();
+    let planets: Vec<Entity> = ports
+        .iter(world)
+        .filter(|(structure, port_owner, _)| {
+            **structure == cmp::Structure::SpacePort && port_owner.0 == owner
+        })
+        .map(|(_, _, planet)| planet.get())
+        .collect();
+    planets
+        .into_iter()
+        .filter_map(|planet| world.get::<cmp::Location>(planet))
+        .map(|loc| (loc.x, loc.y))
+        .collect()
+}
+
+/// Work out the course from `ship` to the star `destination`, refusing it if the ship
+/// would run dry on the way.
+pub fn plan_course(
+    world: &mut World,
+    ship: Entity,
+    destination: Entity,
+) -> Result<CoursePlan, OrderError> {
+    let to = world
+        .get::<cmp::Location>(destination)
+        .map(|loc| (loc.x, loc.y))
+        .ok_or(OrderError::NoDestination)?;
+    let from = world
+        .get::<cmp::Location>(ship)
+        .map(|loc| (loc.x, loc.y))
+        .ok_or(OrderError::NoShip)?;
+    let engine = world
+        .get::<ship::Engine>(ship)
+        .cloned()
+        .ok_or(OrderError::NoShip)?;
+    if world.get::<ship::Disabled>(ship).is_some() {
+        return Err(OrderError::Disabled);
+    }
+    let path = plot_course(from, to);
+    let cost = fuel_cost(engine.fuel_efficiency, path.len() as u32);
+    if cost > engine.current_fuel {
+        return Err(OrderError::OutOfRange {
+            needed: cost,
+            fuel: engine.current_fuel,
+        });
+    }
+    let left = engine.current_fuel - cost;
+    let ports = match world.get::<cmp::Owner>(ship) {
+        Some(owner) => space_port_cells(world, owner.0),
+        None => HashSet::new(),
+    };
+    let strands = !ports.contains(&to)
+        && ports
+            .iter()
+            .all(|&port| fuel_cost(engine.fuel_efficiency, distance(to, port)) > left);
+    Ok(CoursePlan {
+        path,
+        fuel_cost: cost,
+        strands,
+    })
+}
+
+/// Send `ship` towards the star `destination` if it has the fuel to get there.
+pub fn set_course(
+    world: &mut World,
+    ship: Entity,
+    destination: Entity,
+) -> Result<CoursePlan, OrderError> {
+    let plan = plan_course(world, ship, destination)?;
+    log::info!(
+        "ship {:?} heading to {:?}, {} cells for {} fuel",
+        ship,
+        plan.path.back(),
+        plan.path.len(),
+        plan.fuel_cost
+    );
+    if plan.strands {
+        log::warn!("ship {:?} won't be able to refuel after arriving", ship);
+    }
+    world.entity_mut(ship).insert(ship::Course {
+        destination,
+        path: plan.path.clone(),
+    });
+    Ok(plan)
+}
+
+/// Move every ship with a course `speed` cells along it, burning fuel for each, and drop
+/// the course on arrival or when the tank runs dry.
+pub fn move_ships(
+    mut commands: Commands,
+    mut ships: Query<(
+        Entity,
+        &mut ship::Engine,
+        &mut cmp::Location,
+        &mut ship::Course,
+    )>,
+) {
+    for (entity, mut engine, mut loc, mut course) in &mut ships {
+        let cost = fuel_per_cell(engine.fuel_efficiency);
+        for _ in 0..engine.speed {
+            let Some(&(x, y)) = course.path.front() else {
+                break;
+            };
+            if engine.current_fuel < cost {
+                log::warn!(
+                    "ship {:?} ran out of fuel at ({}, {})",
+                    entity,
+                    loc.x,
+                    loc.y
+                );
+                course.path.clear();
+                break;
+            }
+            course.path.pop_front();
+            engine.current_fuel -= cost;
+            loc.x = x;
+            loc.y = y;
+        }
+        if course.path.is_empty() {
+            log::info!("ship {:?} stopped at ({}, {})", entity, loc.x, loc.y);
+            commands.entity(entity).remove::<ship::Course>();
+        }
+    }
+}
+
+/// The cells of every space port, with who owns it.
+fn owned_port_cells(
+    ports: &Query<(&cmp::Structure, &cmp::Owner, &Parent)>,
+    planets: &Query<&cmp::Location>,
+) -> HashSet<(Entity, (u32, u32))> {
+    ports
+        .iter()
+        .filter(|(structure, _, _)| **structure == cmp::Structure::SpacePort)
+        .filter_map(|(_, owner, planet)| {
+            let loc = planets.get(planet.get()).ok()?;
+            Some((owner.0, (loc.x, loc.y)))
+        })
+        .collect()
+}
+
+/// Top up ships sitting at one of their owner's space ports.
+pub fn refuel_ships(
+    mut ships: Query<(&cmp::Owner, &cmp::Location, &mut ship::Engine), Without<ship::Course>>,
+    ports: Query<(&cmp::Structure, &cmp::Owner, &Parent)>,
+    planets: Query<&cmp::Location>,
+) {
+    let port_cells = owned_port_cells(&ports, &planets);
+    for (owner, loc, mut engine) in &mut ships {
+        if engine.current_fuel < engine.max_fuel && port_cells.contains(&(owner.0, (loc.x, loc.y)))
+        {
+            engine.current_fuel = (engine.current_fuel + REFUEL_PER_TICK).min(engine.max_fuel);
+        }
+    }
+}
+
+/// Patch up the hulls of ships sitting at one of their owner's space ports. A disabled
+/// ship is back in service once its hull is out of the disabled range.
+pub fn repair_ships(
+    mut commands: Commands,
+    mut ships: Query<(Entity, &cmp::Owner, &cmp::Location, &mut ship::Hull), Without<ship::Course>>,
+    ports: Query<(&cmp::Structure, &cmp::Owner, &Parent)>,
+    planets: Query<&cmp::Location>,
+    disabled: Query<(), With<ship::Disabled>>,
+) {
+    let port_cells = owned_port_cells(&ports, &planets);
+    for (entity, owner, loc, mut hull) in &mut ships {
+        if hull.current >= hull.max || !port_cells.contains(&(owner.0, (loc.x, loc.y))) {
+            continue;
+        }
+        hull.current = (hull.current + REPAIR_PER_TICK).min(hull.max);
+        if disabled.contains(entity) && hull.current * combat::DISABLED_HULL_FRACTION > hull.max {
+            log::info!("ship {:?} is repaired", entity);
+            commands.entity(entity).remove::<ship::Disabled>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::test_utils;
+
+    #[test]
+    fn test_plot_course() {
+        assert_eq!(plot_course((2, 2), (2, 2)), VecDeque::new());
+        assert_eq!(
+            Vec::from(plot_course((0, 0), (3, 0))),
+            vec![(1, 0), (2, 0), (3, 0)]
+        );
+        assert_eq!(Vec::from(plot_course((3, 3), (1, 1))), vec![(2, 2), (1, 1)]);
+        // every step is to a neighbouring cell and the last one is the destination
+        let path = plot_course((0, 0), (7, 3));
+        assert_eq!(path.len(), 7);
+        assert_eq!(path.back(), Some(&(7, 3)));
+        let mut prev = (0, 0);
+        for &cell in &path {
+            assert!(cell.0.abs_diff(prev.0) <= 1 && cell.1.abs_diff(prev.1) <= 1);
+            prev = cell;
+        }
+    }
+
+    #[test]
+    fn test_ship_flies_to_destination() {
+        let mut app = App::new();
+        app.add_system(move_ships);
+        let player = app.world.spawn_empty().id();
+        let star = app.world.spawn(cmp::Location::at(4, 1)).id();
+        let mut bundle = scout((0, 0));
+        bundle.engine.speed = 2;
+        let ship = app.world.spawn((bundle, cmp::Owner(player))).id();
+        assert!(set_course(&mut app.world, ship, star).is_ok());
+
+        app.update();
+        let loc = app.world.get::<cmp::Location>(ship).unwrap();
+        assert_eq!((loc.x, loc.y), (2, 1));
+        assert!(app.world.get::<ship::Course>(ship).is_some());
+
+        app.update();
+        let loc = app.world.get::<cmp::Location>(ship).unwrap();
+        assert_eq!((loc.x, loc.y), (4, 1));
+        assert!(app.world.get::<ship::Course>(ship).is_none());
+        // four cells at 6 fuel each
+        assert_eq!(
+            app.world.get::<ship::Engine>(ship).unwrap().current_fuel,
+            76
+        );
+
+        let nowhere = app.world.spawn_empty().id();
+        assert_eq!(
+            set_course(&mut app.world, ship, nowhere),
+            Err(OrderError::NoDestination)
+        );
+    }
+
+    #[test]
+    fn test_fuel_cost_and_range() {
+        assert_eq!(fuel_cost(ship::FuelEfficiency::Fossil, 5), 30);
+        assert_eq!(fuel_cost(ship::FuelEfficiency::Solar, 5), 15);
+        assert_eq!(fuel_cost(ship::FuelEfficiency::Deuterium, 5), 10);
+        let mut engine = scout((0, 0)).engine;
+        assert_eq!(range(&engine), 16);
+        engine.fuel_efficiency = ship::FuelEfficiency::Deuterium;
+        assert_eq!(range(&engine), 50);
+        assert_eq!(distance((1, 1), (4, 3)), 3);
+    }
+
+    /// A player with a space port on a planet at (0, 0) and a scout docked there.
+    fn spawn_space_port(world: &mut World) -> (Entity, Entity) {
+        let player = world.spawn_empty().id();
+        let home = test_utils::spawn_planet(world, (0, 0), Some(player));
+        world.entity_mut(home).with_children(|planet| {
+            planet.spawn((cmp::Structure::SpacePort, cmp::Owner(player)));
+        });
+        let ship = world.spawn((scout((0, 0)), cmp::Owner(player))).id();
+        (player, ship)
+    }
+
+    #[test]
+    fn test_orders_that_strand_a_ship() {
+        let mut world = World::new();
+        let (_, ship) = spawn_space_port(&mut world);
+        // 16 cells of fuel: far enough there, but not back
+        let far = world.spawn(cmp::Location::at(12, 0)).id();
+        let plan = plan_course(&mut world, ship, far).unwrap();
+        assert_eq!(plan.fuel_cost, 72);
+        assert!(plan.strands);
+        let near = world.spawn(cmp::Location::at(5, 0)).id();
+        assert!(!plan_course(&mut world, ship, near).unwrap().strands);
+        let too_far = world.spawn(cmp::Location::at(20, 0)).id();
+        assert_eq!(
+            set_course(&mut world, ship, too_far),
+            Err(OrderError::OutOfRange {
+                needed: 120,
+                fuel: 100
+            })
+        );
+        assert!(world.get::<ship::Course>(ship).is_none());
+    }
+
+    #[test]
+    fn test_refuel_at_space_port() {
+        let mut app = App::new();
+        app.add_system(refuel_ships);
+        let world = &mut app.world;
+        let (player, ship) = spawn_space_port(world);
+        let stray = world.spawn((scout((3, 0)), cmp::Owner(player))).id();
+        for ship in [ship, stray] {
+            world.get_mut::<ship::Engine>(ship).unwrap().current_fuel = 10;
+        }
+        app.update();
+        assert_eq!(
+            app.world.get::<ship::Engine>(ship).unwrap().current_fuel,
+            35
+        );
+        assert_eq!(
+            app.world.get::<ship::Engine>(stray).unwrap().current_fuel,
+            10
+        );
+        for _ in 0..5 {
+            app.update();
+        }
+        assert_eq!(
+            app.world.get::<ship::Engine>(ship).unwrap().current_fuel,
+            100
+        );
+    }
+
+    #[test]
+    fn test_disabled_ship_repaired_at_space_port() {
+        let mut app = App::new();
+        app.add_system(repair_ships);
+        let world = &mut app.world;
+        let (player, ship) = spawn_space_port(world);
+        let stray = world.spawn((scout((3, 0)), cmp::Owner(player))).id();
+        for ship in [ship, stray] {
+            world.get_mut::<ship::Hull>(ship).unwrap().current = 2;
+            world.entity_mut(ship).insert(ship::Disabled);
+        }
+        // a 20 point hull is disabled at 5 and below
+        app.update();
+        assert!(app.world.get::<ship::Disabled>(ship).is_some());
+        app.update();
+        assert_eq!(app.world.get::<ship::Hull>(ship).unwrap().current, 6);
+        assert!(app.world.get::<ship::Disabled>(ship).is_none());
+        assert!(app.world.get::<ship::Disabled>(stray).is_some());
+        assert_eq!(app.world.get::<ship::Hull>(stray).unwrap().current, 2);
+
+        for _ in 0..10 {
+            app.update();
+        }
+        assert_eq!(app.world.get::<ship::Hull>(ship).unwrap().current, 20);
+    }
+}
@@ -0,0 +1,227 @@
+use bevy::prelude::*;
+use rand::prelude::*;
+
+use crate::components::{
+    self as cmp,
+    astronomy::{Body, PlanetClass, StellarObj},
+};
+
+const ASTEROID_BELT_CHANCE: f64 = 0.15;
+
+fn roman_numeral(mut n: u32) -> String {
+    const NUMERALS: [(u32, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut numeral = String::new();
+    for (value, symbol) in NUMERALS {
+        while n >= value {
+            numeral.push_str(symbol);
+            n -= value;
+        }
+    }
+    numeral
+}
+
+/// Planets close to the star are hot and rocky, the outer ones cold or gaseous.
+fn planet_class<R: Rng>(slot: u32, num_slots: u32, rng: &mut R) -> PlanetClass {
+    let distance = slot as f64 / num_slots.max(1) as f64;
+    let choices: &[PlanetClass] = if distance <= 0.34 {
+        &[PlanetClass::Barren, PlanetClass::Desert, PlanetClass::Rocky]
+    } else if distance <= 0.67 {
+        &[PlanetClass::Rocky, PlanetClass::Ocean, PlanetClass::Desert]
+    } else {
+        &[PlanetClass::GasGiant, PlanetClass::Ice, PlanetClass::Barren]
+    };
+    *choices.choose(rng).unwrap()
+}
+
+fn random_body<R: Rng>(class: PlanetClass, rng: &mut R) -> Body {
+    let (size, habitability) = match class {
+        PlanetClass::Barren => (rng.gen_range(1..=4), rng.gen_range(0..=10)),
+        PlanetClass::Desert => (rng.gen_range(2..=6), rng.gen_range(10..=40)),
+        PlanetClass::Rocky => (rng.gen_range(2..=6), rng.gen_range(30..=70)),
+        PlanetClass::Ocean => (rng.gen_range(3..=6), rng.gen_range(60..=100)),
+        PlanetClass::Ice => (rng.gen_range(1..=5), rng.gen_range(0..=20)),
+        PlanetClass::GasGiant => (rng.gen_range(8..=12), 0),
+    };
+    Body {
+        size,
+        habitability,
+        minerals: rng.gen_range(0..=100),
+    }
+}
+
+fn orbit_location(star_location: &cmp::Location, z: u32, w: u32) -> cmp::Location {
+    cmp::Location {
+        z,
+        w,
+        ..*star_location
+    }
+}
+
+/// Spawn the planets, moons and asteroid belts orbiting `star` as its children, planets
+/// and belts in orbital slots 1..=n and moons as children of their planet. Returns the
+/// planets, e.g. to pick homeworlds from. At least `min_planets` of them, as far as
+/// `max_planets` allows.
+pub fn spawn_star_system<R: Rng>(
+    commands: &mut Commands,
+    star: Entity,
+    star_name: &str,
+    star_location: &cmp::Location,
+    min_planets: u32,
+    max_planets: u32,
+    rng: &mut R,
+) -> Vec<(Entity, Body)> {
+    let num_slots = rng.gen_range(min_planets.min(max_planets)..=max_planets);
+    let mut planets = Vec::new();
+    commands.entity(star).with_children(|system| {
+        for slot in 1..=num_slots {
+            // the slots left are all needed for planets
+            let planet_needed = num_slots - slot < min_planets.saturating_sub(planets.len() as u32);
+            if !planet_needed && rng.gen_bool(ASTEROID_BELT_CHANCE) {
+                system.spawn((
+                    StellarObj::AsteroidBelt,
+                    orbit_location(star_location, slot, 0),
+                    Body {
+                        size: rng.gen_range(1..=3),
+                        habitability: 0,
+                        minerals: rng.gen_range(50..=100),
+                    },
+                    cmp::Name(format!("{} Belt {}", star_name, roman_numeral(slot))),
+                ));
+                continue;
+            }
+
+            let class = planet_class(slot, num_slots, rng);
+            let planet_name = format!("{} {}", star_name, roman_numeral(slot));
+            let max_moons = if class == PlanetClass::GasGiant { 4 } else { 2 };
+            let num_moons = rng.gen_range(0..=max_moons);
+            let body = random_body(class, rng);
+            let mut planet = system.spawn((
+                StellarObj::Planet,
+                orbit_location(star_location, slot, 0),
+                class,
+                body,
+                cmp::Name(planet_name.clone()),
+            ));
+            planets.push((planet.id(), body));
+            planet.with_children(|planet| {
+                for moon_slot in 1..=num_moons {
+                    let moon_class = *[PlanetClass::Barren, PlanetClass::Ice, PlanetClass::Rocky]
+                        .choose(rng)
+                        .unwrap();
+                    let mut body = random_body(moon_class, rng);
+                    body.size = body.size.min(2);
+                    // moons are lettered like exoplanets: the first one is 'b'
+                    let letter = (b'a' + moon_slot as u8) as char;
+                    planet.spawn((
+                        StellarObj::Moon,
+                        orbit_location(star_location, slot, moon_slot),
+                        moon_class,
+                        body,
+                        cmp::Name(format!("{} {}", planet_name, letter)),
+                    ));
+                }
+            });
+        }
+    });
+    planets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bevy::ecs::system::CommandQueue;
+    use pretty_assertions::assert_eq;
+    use rand_chacha::ChaCha8Rng;
+    use std::collections::HashSet;
+
+    use crate::components::astronomy::GalacticObj;
+
+    fn spawn_system(seed: u64, min_planets: u32) -> (World, Entity) {
+        let mut world = World::new();
+        let location = cmp::Location {
+            x: 3,
+            y: 4,
+            z: 0,
+            w: 0,
+            ui_offset: (0., 0.),
+        };
+        let star = world
+            .spawn((GalacticObj::Star, location.clone(), cmp::Name("Sol".into())))
+            .id();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        spawn_star_system(
+            &mut commands,
+            star,
+            "Sol",
+            &location,
+            min_planets,
+            8,
+            &mut rng,
+        );
+        queue.apply(&mut world);
+        (world, star)
+    }
+
+    #[test]
+    fn test_roman_numeral() {
+        assert_eq!(roman_numeral(1), "I");
+        assert_eq!(roman_numeral(4), "IV");
+        assert_eq!(roman_numeral(9), "IX");
+        assert_eq!(roman_numeral(14), "XIV");
+    }
+
+    #[test]
+    fn test_star_system_hierarchy() {
+        for seed in 0..20 {
+            let (mut world, star) = spawn_system(seed, 0);
+            let mut query = world.query::<(&StellarObj, &Parent, &cmp::Location)>();
+            let mut slots = HashSet::new();
+            for (stellar_obj, parent, loc) in query.iter(&world) {
+                assert_eq!((loc.x, loc.y), (3, 4));
+                match stellar_obj {
+                    StellarObj::Planet | StellarObj::AsteroidBelt => {
+                        assert_eq!(parent.get(), star);
+                        assert_eq!(loc.w, 0);
+                    }
+                    StellarObj::Moon => {
+                        let planet = world.get::<StellarObj>(parent.get());
+                        assert_eq!(planet, Some(&StellarObj::Planet));
+                        assert_ne!(loc.w, 0);
+                    }
+                }
+                assert!(loc.z >= 1 && loc.z <= 8);
+                assert!(slots.insert((loc.z, loc.w)), "slot taken twice");
+            }
+        }
+    }
+
+    #[test]
+    fn test_min_planets() {
+        for seed in 0..20 {
+            let (mut world, _) = spawn_system(seed, 3);
+            let planets = world
+                .query::<&StellarObj>()
+                .iter(&world)
+                .filter(|&&stellar_obj| stellar_obj == StellarObj::Planet)
+                .count();
+            assert!(planets >= 3, "only {} planets", planets);
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use bevy::prelude::*;
+
+use crate::components::{self as cmp, astronomy::GalacticObj};
+
+/// A star at `cell` with one planet in the first orbital slot, returning the planet.
+pub fn spawn_planet(world: &mut World, cell: (u32, u32), owner: Option<Entity>) -> Entity {
+    let star = world
+        .spawn((GalacticObj::Star, cmp::Location::at(cell.0, cell.1)))
+        .id();
+    let planet = world
+        .spawn(cmp::Location {
+            z: 1,
+            ..cmp::Location::at(cell.0, cell.1)
+        })
+        .id();
+    if let Some(owner) = owner {
+        world.entity_mut(planet).insert(cmp::Owner(owner));
+    }
+    world.entity_mut(star).push_children(&[planet]);
+    planet
+}
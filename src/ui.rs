@@ -1,26 +1,37 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
 use tui::{
     backend::Backend,
     layout::{self, Constraint, Layout, Rect},
-    style::Color,
-    text::Spans,
+    style::{Color, Style},
+    text::{Span, Spans},
     widgets::{
-        canvas::{Canvas, Line, Painter, Shape},
-        Block, Borders, Clear, Paragraph,
+        canvas::{self, Canvas, Line, Painter, Shape},
+        Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap,
     },
     Frame,
 };
+use tui_textarea::TextArea;
 
-use bevy::prelude::App;
+use bevy::prelude::{App, Children, Entity, Parent, With};
 use log;
 
-use crate::{components as cmp, resources};
+use crate::{clock, combat, components as cmp, construction, fog, resources, search, ships};
 
 pub struct TuiState {
     pub active_modal: Modal,
     pub active_view: View,
     pub galaxy_view: GalaxyView,
+    pub system_view: SystemView,
+    pub search: SearchState,
+    /// Which combat report is open, 0 being the newest.
+    pub report_idx: usize,
 }
 
+/// How much one zoom step scales the canvas by. Steps multiply so zooming feels the same
+/// at every scale.
+const ZOOM_STEP: f64 = 1.25;
+
 pub struct CanvasCamera {
     pub frame_size: (f64, f64),
     pub canvas_size: (f64, f64),
@@ -39,8 +50,8 @@ impl CanvasCamera {
             canvas_size: frame_size,
             origin: (0., 0.),
             scale: 1.,
-            max_scale: 3.,
-            min_scale: 1.,
+            max_scale: 12.,
+            min_scale: 0.5,
             canvas_center: (frame_size.0 / 2., frame_size.1 / 2.),
             frame_center: (frame_size.0 / 2., frame_size.1 / 2.),
         }
@@ -56,31 +67,137 @@ impl CanvasCamera {
             (self.origin.1 + self.canvas_size.1) / 2.,
         );
     }
-    pub fn zoom_in(&mut self) {
-        // find the canvas point that is centered in the frame, scale it, and center it again in the frame
-        if self.scale == self.max_scale {
+    /// Linear interpolation of a point from range [a,b] (the galaxy grid)
+    /// to range [c,d] (the canvas).
+    pub fn grid_to_canvas(&self, point: (f64, f64), galaxy_dimension: u32) -> (f64, f64) {
+        let (a, b) = (0., (galaxy_dimension as f64 - 1.).max(1.));
+        let (cx, dx) = (self.origin.0, self.canvas_size.0 + self.origin.0);
+        let (cy, dy) = (self.origin.1, self.canvas_size.1 + self.origin.1);
+        let f_of_x = |p: f64| ((p - a) * ((dx - cx) / (b - a))) + cx;
+        let f_of_y = |p: f64| ((p - a) * ((dy - cy) / (b - a))) + cy;
+        (f_of_x(point.0), f_of_y(point.1))
+    }
+
+    /// Change the frame size, e.g. when the terminal is resized, keeping whatever is in the
+    /// middle of the view in the middle.
+    pub fn resize(&mut self, frame_size: (f64, f64)) {
+        let (canvas_width, canvas_height) = self.canvas_size;
+        if canvas_width <= 0. || canvas_height <= 0. {
+            // nothing was visible yet, so there's no point to keep in view
+            self.frame_size = frame_size;
+            self.update();
             return;
         }
-        self.scale += 0.25;
+        // how far across the canvas the middle of the view is, which doesn't change
+        let fraction = (
+            (self.frame_center.0 - self.origin.0) / canvas_width,
+            (self.frame_center.1 - self.origin.1) / canvas_height,
+        );
+        self.frame_size = frame_size;
+        self.update();
+        self.origin = (
+            self.frame_center.0 - fraction.0 * self.canvas_size.0,
+            self.frame_center.1 - fraction.1 * self.canvas_size.1,
+        );
+        self.update();
+    }
+
+    /// The inverse of `grid_to_canvas`: the galaxy grid point drawn at `canvas_point`.
+    pub fn canvas_to_grid(&self, canvas_point: (f64, f64), galaxy_dimension: u32) -> (f64, f64) {
+        let cells = (galaxy_dimension as f64 - 1.).max(1.);
+        (
+            (canvas_point.0 - self.origin.0) * cells / self.canvas_size.0,
+            (canvas_point.1 - self.origin.1) * cells / self.canvas_size.1,
+        )
+    }
+
+    /// The canvas point in the middle of the terminal cell (column, row), or `None` outside
+    /// the canvas. The frame is drawn at the top left of the terminal with a border around
+    /// it, and the canvas fills the braille dots inside, so this undoes tui's mapping from
+    /// canvas points to dots.
+    pub fn screen_to_canvas(&self, (column, row): (u16, u16)) -> Option<(f64, f64)> {
+        let (width, height) = self.frame_size;
+        let (inner_width, inner_height) = (width - 2., height - 2.);
+        let (column, row) = (column as f64 - 1., row as f64 - 1.);
+        if column < 0. || row < 0. || column >= inner_width || row >= inner_height {
+            return None;
+        }
+        let dots_x = inner_width * 2. - 1.;
+        let dots_y = inner_height * 4. - 1.;
+        Some((
+            (column * 2. + 1.) * width / dots_x,
+            height - (row * 4. + 2.) * height / dots_y,
+        ))
     }
 
-    pub fn zoom_out(&mut self) {
-        if self.scale == self.min_scale {
+    /// Move the whole canvas by a number of terminal cells, as when dragging it.
+    pub fn pan_by_cells(&mut self, columns: i32, rows: i32) {
+        let (width, height) = self.frame_size;
+        // a frame too small to have an inside has nothing to drag
+        if width - 2. <= 0. || height - 2. <= 0. {
             return;
         }
-        self.scale -= 0.25;
+        self.origin.0 += columns as f64 * width / (width - 2.);
+        self.origin.1 -= rows as f64 * height / (height - 2.);
+        self.update();
+    }
+
+    /// Move the origin just enough that `canvas_point` is at least `margin` inside the frame.
+    pub fn pan_to_include(&mut self, canvas_point: (f64, f64), margin: f64) {
+        let shift = |p: f64, size: f64| {
+            if p < margin {
+                margin - p
+            } else if p > size - margin {
+                size - margin - p
+            } else {
+                0.
+            }
+        };
+        self.origin.0 += shift(canvas_point.0, self.frame_size.0);
+        self.origin.1 += shift(canvas_point.1, self.frame_size.1);
+        self.update();
+    }
+
+    /// Move the origin so `canvas_point` ends up in the middle of the frame.
+    pub fn center_on(&mut self, canvas_point: (f64, f64)) {
+        self.origin.0 += self.frame_center.0 - canvas_point.0;
+        self.origin.1 += self.frame_center.1 - canvas_point.1;
+        self.update();
+    }
+
+    /// Multiply the scale by `factor`, clamped to the scale limits, moving the origin so
+    /// whatever is under `anchor` (a canvas point) stays put on screen.
+    pub fn zoom(&mut self, factor: f64, anchor: (f64, f64)) {
+        let scale = (self.scale * factor).clamp(self.min_scale, self.max_scale);
+        // a point's distance from the origin grows with the scale, so shrink or grow the
+        // anchor's distance from the origin by the same ratio
+        let ratio = scale / self.scale;
+        self.origin.0 = anchor.0 - (anchor.0 - self.origin.0) * ratio;
+        self.origin.1 = anchor.1 - (anchor.1 - self.origin.1) * ratio;
+        self.scale = scale;
+        self.update();
+    }
+
+    pub fn zoom_in(&mut self, anchor: (f64, f64)) {
+        self.zoom(ZOOM_STEP, anchor);
+    }
+
+    pub fn zoom_out(&mut self, anchor: (f64, f64)) {
+        self.zoom(1. / ZOOM_STEP, anchor);
     }
 }
 
 #[derive(PartialEq)]
 pub enum View {
     Galaxy,
+    System,
 }
 
 #[derive(PartialEq)]
 pub enum Modal {
     Help,
     SearchObj,
+    CombatReport,
     Off,
 }
 
@@ -91,6 +208,15 @@ pub struct GalaxyView {
     pub target_astro_obj: Option<(u32, u32)>,
     pub show_ids: bool,
     pub camera: CanvasCamera,
+    /// Where the left mouse button went down and whether it has moved since, to tell a
+    /// click from a drag.
+    pub mouse_down: Option<(u16, u16)>,
+    pub dragged: bool,
+    /// The ship orders go to, picked separately from the selected object so a star can
+    /// be chosen as its destination.
+    pub selected_ship: Option<Entity>,
+    /// What became of the last order given to the selected ship, e.g. why it was refused.
+    pub order_message: Option<String>,
 }
 
 impl GalaxyView {
@@ -102,62 +228,674 @@ impl GalaxyView {
             target_astro_obj: None,
             show_ids: false,
             camera: CanvasCamera::new(frame_size),
+            mouse_down: None,
+            dragged: false,
+            selected_ship: None,
+            order_message: None,
+        }
+    }
+
+    fn select(&mut self, idx: usize) {
+        self.selected_idx = idx;
+        self.selected_astro_obj = self.astro_objs.get(idx).copied();
+        log::debug!("selected astro obj {:?}", self.selected_astro_obj);
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.astro_objs.is_empty() {
+            self.select((self.selected_idx + 1) % self.astro_objs.len());
         }
     }
+
+    pub fn select_prev(&mut self) {
+        if !self.astro_objs.is_empty() {
+            let len = self.astro_objs.len();
+            self.select((self.selected_idx + len - 1) % len);
+        }
+    }
+
+    /// Select the object closest to the current one in the direction (dx, dy) of the grid.
+    /// Objects off to the side count as further away than ones straight ahead.
+    pub fn select_direction(&mut self, direction: (i64, i64)) {
+        let Some((x, y)) = self.selected_astro_obj else {
+            return;
+        };
+        let nearest = self
+            .astro_objs
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, &(ox, oy))| {
+                let (dx, dy) = (ox as i64 - x as i64, oy as i64 - y as i64);
+                let along = dx * direction.0 + dy * direction.1;
+                let across = (dx * direction.1 - dy * direction.0).abs();
+                (along > 0).then_some((along + 2 * across, idx))
+            })
+            .min();
+        if let Some((_, idx)) = nearest {
+            self.select(idx);
+        }
+    }
+
+    /// Replace the selectable objects, e.g. as more of the galaxy is explored, keeping the
+    /// current selection if it's still among them.
+    pub fn set_astro_objs(&mut self, astro_objs: Vec<(u32, u32)>) {
+        if astro_objs == self.astro_objs {
+            return;
+        }
+        self.astro_objs = astro_objs;
+        let selected = self
+            .selected_astro_obj
+            .and_then(|cell| self.astro_objs.iter().position(|&obj| obj == cell));
+        self.select(selected.unwrap_or(0));
+    }
+
+    /// Select the object closest to a point on the galaxy grid.
+    pub fn select_nearest(&mut self, grid_point: (f64, f64)) {
+        let distance = |&(x, y): &(u32, u32)| {
+            (x as f64 - grid_point.0).powi(2) + (y as f64 - grid_point.1).powi(2)
+        };
+        let nearest = self
+            .astro_objs
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| distance(a).total_cmp(&distance(b)));
+        if let Some((idx, _)) = nearest {
+            self.select(idx);
+        }
+    }
+
+    /// Select the object in `cell`, returning whether there is one.
+    pub fn select_cell(&mut self, cell: (u32, u32)) -> bool {
+        match self.astro_objs.iter().position(|&obj| obj == cell) {
+            Some(idx) => {
+                self.select(idx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Pan the camera so the grid cell `(x, y)` is in the middle of the frame.
+    pub fn center_on_cell(&mut self, (x, y): (u32, u32), galaxy_dimension: u32) {
+        let canvas_point = self
+            .camera
+            .grid_to_canvas((x as f64, y as f64), galaxy_dimension);
+        self.camera.center_on(canvas_point);
+    }
+
+    /// The canvas point zooming should keep still: the selected object while it's on
+    /// screen, otherwise the middle of the frame.
+    pub fn zoom_anchor(&self, galaxy_dimension: u32) -> (f64, f64) {
+        let camera = &self.camera;
+        self.selected_astro_obj
+            .map(|(x, y)| camera.grid_to_canvas((x as f64, y as f64), galaxy_dimension))
+            .filter(|&(x, y)| {
+                (0. ..=camera.frame_size.0).contains(&x) && (0. ..=camera.frame_size.1).contains(&y)
+            })
+            .unwrap_or(camera.frame_center)
+    }
+
+    /// Pan the camera so the selected object is on screen.
+    pub fn pan_to_selected(&mut self, galaxy_dimension: u32) {
+        if let Some((x, y)) = self.selected_astro_obj {
+            let canvas_point = self
+                .camera
+                .grid_to_canvas((x as f64, y as f64), galaxy_dimension);
+            self.camera.pan_to_include(canvas_point, 4.);
+        }
+    }
+
+    /// Select the ship after the current one in `ships`, wrapping around.
+    pub fn select_next_ship(&mut self, ships: &[Entity]) {
+        let next = self
+            .selected_ship
+            .and_then(|ship| ships.iter().position(|&other| other == ship))
+            .map_or(0, |idx| idx + 1);
+        self.selected_ship = ships.get(next % ships.len().max(1)).copied();
+        self.order_message = None;
+        log::debug!("selected ship {:?}", self.selected_ship);
+    }
+}
+
+/// The inside of a single star system, opened from the galaxy view.
+pub struct SystemView {
+    pub star: Option<Entity>,
+    pub selected_idx: usize,
+    /// The kind of cargo load and unload orders move.
+    pub cargo_kind: cmp::Cargo,
+    /// Planets picked so far for the selected ship's trade route.
+    pub route_stops: Vec<Entity>,
+    /// The kind of structure build orders queue.
+    pub structure_kind: cmp::Structure,
+    /// The planet picked to pipe resources from, waiting for a planet to pipe them to.
+    pub pipeline_from: Option<Entity>,
+}
+
+impl SystemView {
+    fn new() -> SystemView {
+        SystemView {
+            star: None,
+            selected_idx: 0,
+            cargo_kind: cmp::Cargo::Resources,
+            route_stops: Vec::new(),
+            structure_kind: cmp::Structure::Factory,
+            pipeline_from: None,
+        }
+    }
+
+    pub fn next_structure_kind(&mut self) {
+        let idx = cmp::Structure::ALL
+            .iter()
+            .position(|&kind| kind == self.structure_kind)
+            .unwrap_or(0);
+        self.structure_kind = cmp::Structure::ALL[(idx + 1) % cmp::Structure::ALL.len()];
+    }
+
+    /// Move load and unload orders on to the next kind of cargo.
+    pub fn next_cargo_kind(&mut self) {
+        let idx = cmp::Cargo::ALL
+            .iter()
+            .position(|&kind| kind == self.cargo_kind)
+            .unwrap_or(0);
+        self.cargo_kind = cmp::Cargo::ALL[(idx + 1) % cmp::Cargo::ALL.len()];
+    }
+
+    /// The trade route through `route_stops`: load the chosen cargo at the first stop and
+    /// unload it at every other.
+    pub fn trade_stops(&self) -> Vec<cmp::ship::TradeStop> {
+        self.route_stops
+            .iter()
+            .enumerate()
+            .map(|(idx, &planet)| cmp::ship::TradeStop {
+                planet,
+                unload: if idx == 0 {
+                    vec![]
+                } else {
+                    vec![self.cargo_kind]
+                },
+                load: if idx == 0 {
+                    vec![self.cargo_kind]
+                } else {
+                    vec![]
+                },
+            })
+            .collect()
+    }
+}
+
+/// The object search modal: the query being typed and the objects matching it.
+pub struct SearchState {
+    pub textarea: TextArea<'static>,
+    /// The best matches, at most `search::MAX_RESULTS` of them.
+    pub results: Vec<search::SearchResult>,
+    /// How many objects matched, including those cut off the list.
+    pub num_matches: usize,
+    pub selected_idx: usize,
+}
+
+impl SearchState {
+    fn new() -> SearchState {
+        let mut textarea = TextArea::default();
+        textarea.set_placeholder_text("type a name");
+        textarea.set_block(Block::default().borders(Borders::ALL).title("Search"));
+        SearchState {
+            textarea,
+            results: Vec::new(),
+            num_matches: 0,
+            selected_idx: 0,
+        }
+    }
+
+    pub fn query(&self) -> &str {
+        self.textarea
+            .lines()
+            .first()
+            .map_or("", |line| line.as_str())
+    }
+
+    /// Re-run the search for the current query, keeping the best match highlighted.
+    pub fn update_results(&mut self, app: &mut App) {
+        self.results = search::search_objs(app, self.query());
+        self.num_matches = self.results.len();
+        self.results.truncate(search::MAX_RESULTS);
+        self.selected_idx = 0;
+    }
+
+    pub fn selected(&self) -> Option<&search::SearchResult> {
+        self.results.get(self.selected_idx)
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected_idx + 1 < self.results.len() {
+            self.selected_idx += 1;
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        self.selected_idx = self.selected_idx.saturating_sub(1);
+    }
+}
+
+/// A planet, moon or asteroid belt as shown in the system view.
+pub struct SystemBody {
+    pub entity: Entity,
+    pub name: String,
+    pub stellar_obj: cmp::astronomy::StellarObj,
+    pub planet_class: Option<cmp::astronomy::PlanetClass>,
+    pub slot: u32,
+    pub moon_slot: u32,
+}
+
+/// Everything orbiting `star`, ordered by orbital slot with each planet's moons after it.
+pub fn query_system_bodies(app: &App, star: Entity) -> Vec<SystemBody> {
+    let world = &app.world;
+    let to_body = |entity: Entity| {
+        let stellar_obj = *world.get::<cmp::astronomy::StellarObj>(entity)?;
+        let loc = world.get::<cmp::Location>(entity)?;
+        Some(SystemBody {
+            entity,
+            name: world
+                .get::<cmp::Name>(entity)
+                .map(|name| name.0.clone())
+                .unwrap_or_default(),
+            stellar_obj,
+            planet_class: world.get::<cmp::astronomy::PlanetClass>(entity).copied(),
+            slot: loc.z,
+            moon_slot: loc.w,
+        })
+    };
+    let children = |entity: Entity| {
+        world
+            .get::<Children>(entity)
+            .map(|children| children.iter().copied().collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+    let mut bodies: Vec<SystemBody> = children(star)
+        .into_iter()
+        .flat_map(|entity| std::iter::once(entity).chain(children(entity)))
+        .filter_map(to_body)
+        .collect();
+    bodies.sort_by_key(|body| (body.slot, body.moon_slot));
+    bodies
+}
+
+/// What the info panel shows about the object in the selected cell.
+pub struct ObjInfo {
+    pub name: String,
+    pub galactic_obj: cmp::astronomy::GalacticObj,
+    pub location: cmp::Location,
+    pub owner: Option<cmp::Player>,
+    pub bodies: Vec<SystemBody>,
+}
+
+/// The galactic object in `cell` and everything orbiting it.
+pub fn query_obj_info(app: &mut App, cell: (u32, u32)) -> Option<ObjInfo> {
+    let (entity, name, galactic_obj, location, owner) = app
+        .world
+        .query::<(
+            Entity,
+            Option<&cmp::Name>,
+            &cmp::astronomy::GalacticObj,
+            &cmp::Location,
+            Option<&cmp::Owner>,
+        )>()
+        .iter(&app.world)
+        .find(|(_, _, _, loc, _)| (loc.x, loc.y) == cell)
+        .map(|(entity, name, galactic_obj, loc, owner)| {
+            let name = name.map(|name| name.0.clone()).unwrap_or_default();
+            (entity, name, *galactic_obj, loc.clone(), owner.copied())
+        })?;
+    Some(ObjInfo {
+        name,
+        galactic_obj,
+        location,
+        owner: owner.and_then(|owner| app.world.get::<cmp::Player>(owner.0).cloned()),
+        bodies: query_system_bodies(app, entity),
+    })
+}
+
+/// Every player's color, to draw what they own in.
+fn query_player_colors(app: &mut App) -> HashMap<Entity, Color> {
+    app.world
+        .query::<(Entity, &cmp::Player)>()
+        .iter(&app.world)
+        .map(|(entity, player)| (entity, player_color(player.color)))
+        .collect()
+}
+
+/// The star at the given galaxy grid cell, if there is one.
+pub fn star_at(app: &mut App, cell: (u32, u32)) -> Option<Entity> {
+    app.world
+        .query::<(Entity, &cmp::astronomy::GalacticObj, &cmp::Location)>()
+        .iter(&app.world)
+        .find(|(_, galactic_obj, loc)| {
+            **galactic_obj == cmp::astronomy::GalacticObj::Star && (loc.x, loc.y) == cell
+        })
+        .map(|(entity, _, _)| entity)
+}
+
+/// The ships the local player gives orders to, or every ship when nobody is playing.
+pub fn query_local_ships(app: &mut App) -> Vec<Entity> {
+    let local_player = app
+        .world
+        .get_resource::<resources::LocalPlayer>()
+        .map(|player| player.0);
+    let mut ships: Vec<Entity> = app
+        .world
+        .query_filtered::<(Entity, Option<&cmp::Owner>), With<cmp::ship::Engine>>()
+        .iter(&app.world)
+        .filter(|(_, owner)| {
+            local_player.is_none_or(|player| owner.is_some_and(|owner| owner.0 == player))
+        })
+        .map(|(entity, _)| entity)
+        .collect();
+    ships.sort();
+    ships
+}
+
+/// What the info panel shows about the selected ship.
+pub struct ShipInfo {
+    pub name: String,
+    pub cell: (u32, u32),
+    pub owner: Option<cmp::Player>,
+    /// The star the ship is flying to and how many cells it has left.
+    pub destination: Option<(String, usize)>,
+    pub engine: Option<cmp::ship::Engine>,
+    pub cargo_bay: Option<cmp::ship::CargoBay>,
+    pub hull: Option<cmp::ship::Hull>,
+    pub shields: Option<cmp::ship::Shields>,
+    pub disabled: bool,
+    /// The names of the planets on the ship's trade route.
+    pub trade_route: Vec<String>,
+}
+
+pub fn query_ship_info(app: &App, ship: Entity) -> Option<ShipInfo> {
+    let world = &app.world;
+    let loc = world.get::<cmp::Location>(ship)?;
+    let name_of = |entity: Entity| {
+        world
+            .get::<cmp::Name>(entity)
+            .map(|name| name.0.clone())
+            .unwrap_or_default()
+    };
+    Some(ShipInfo {
+        name: name_of(ship),
+        cell: (loc.x, loc.y),
+        owner: world
+            .get::<cmp::Owner>(ship)
+            .and_then(|owner| world.get::<cmp::Player>(owner.0).cloned()),
+        destination: world
+            .get::<cmp::ship::Course>(ship)
+            .map(|course| (name_of(course.destination), course.path.len())),
+        engine: world.get::<cmp::ship::Engine>(ship).cloned(),
+        cargo_bay: world.get::<cmp::ship::CargoBay>(ship).cloned(),
+        hull: world.get::<cmp::ship::Hull>(ship).copied(),
+        shields: world.get::<cmp::ship::Shields>(ship).copied(),
+        disabled: world.get::<cmp::ship::Disabled>(ship).is_some(),
+        trade_route: world
+            .get::<cmp::ship::TradeRoute>(ship)
+            .map(|route| {
+                route
+                    .stops
+                    .iter()
+                    .map(|stop| name_of(stop.planet))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    })
+}
+
+/// A connected pipeline, as the galaxy view draws it.
+pub struct PipelineInfo {
+    pub owner: Entity,
+    pub from_name: String,
+    pub to_name: String,
+    pub from_cell: (u32, u32),
+    pub to_cell: (u32, u32),
+    pub link: cmp::structure::PipelineLink,
+}
+
+/// The local player's connected pipelines, or everyone's when there's no local player.
+pub fn query_pipelines(app: &mut App) -> Vec<PipelineInfo> {
+    let local_player = app
+        .world
+        .get_resource::<resources::LocalPlayer>()
+        .map(|player| player.0);
+    let links: Vec<(Entity, Entity, cmp::structure::PipelineLink)> = app
+        .world
+        .query::<(&cmp::Owner, &Parent, &cmp::structure::PipelineLink)>()
+        .iter(&app.world)
+        .filter(|(owner, _, _)| local_player.is_none_or(|player| owner.0 == player))
+        .map(|(owner, planet, link)| (owner.0, planet.get(), *link))
+        .collect();
+    let world = &app.world;
+    let name_of = |entity: Entity| {
+        world
+            .get::<cmp::Name>(entity)
+            .map(|name| name.0.clone())
+            .unwrap_or_default()
+    };
+    let cell_of = |entity: Entity| world.get::<cmp::Location>(entity).map(|loc| (loc.x, loc.y));
+    links
+        .into_iter()
+        .filter_map(|(owner, planet, link)| {
+            Some(PipelineInfo {
+                owner,
+                from_name: name_of(planet),
+                to_name: name_of(link.to),
+                from_cell: cell_of(planet)?,
+                to_cell: cell_of(link.to)?,
+                link,
+            })
+        })
+        .collect()
+}
+
+/// The reports of battles the local player fought in, newest first.
+pub fn query_combat_reports(app: &App) -> Vec<combat::CombatReport> {
+    let Some(combat_log) = app.world.get_resource::<combat::CombatLog>() else {
+        return Vec::new();
+    };
+    let local_player = app
+        .world
+        .get_resource::<resources::LocalPlayer>()
+        .map(|player| player.0);
+    combat_log
+        .reports
+        .iter()
+        .rev()
+        .filter(|report| {
+            local_player.is_none_or(|player| report.ships.iter().any(|ship| ship.owner == player))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Every galactic object's grid cell in reading order, top row first, so cycling through
+/// them sweeps across the screen.
+/// Under fog of war only the objects the local player has explored are listed.
+fn query_astro_objs(app: &mut App) -> Vec<(u32, u32)> {
+    let mut astro_objs: Vec<(u32, u32)> = app
+        .world
+        .query::<(&cmp::astronomy::GalacticObj, &cmp::Location)>()
+        .iter(&app.world)
+        .map(|(_, loc)| (loc.x, loc.y))
+        .collect();
+    if let Some(fog) = fog::local_fog(&app.world) {
+        astro_objs.retain(|cell| fog.explored.contains(cell));
+    }
+    astro_objs.sort_by_key(|&(x, y)| (std::cmp::Reverse(y), x));
+    astro_objs
 }
 
 impl TuiState {
     pub fn new(app: &mut App) -> TuiState {
-        let astro_objs: Vec<(u32, u32)> = app
-            .world
-            .query::<(&cmp::astronomy::GalacticObj, &cmp::Location)>()
-            .iter(&app.world)
-            .map(|(_, loc)| (loc.x, loc.y))
-            .collect();
+        let astro_objs = query_astro_objs(app);
         TuiState {
             galaxy_view: GalaxyView {
                 selected_idx: 0,
-                selected_astro_obj: Some(astro_objs[0]),
+                selected_astro_obj: astro_objs.first().copied(),
                 astro_objs,
                 camera: CanvasCamera::new((0., 0.)),
                 show_ids: false,
                 target_astro_obj: None,
+                mouse_down: None,
+                dragged: false,
+                selected_ship: None,
+                order_message: None,
             },
+            system_view: SystemView::new(),
+            search: SearchState::new(),
+            report_idx: 0,
             active_modal: Modal::Off,
             active_view: View::Galaxy,
         }
     }
+
+    /// Re-read the galaxy after the world was replaced wholesale, e.g. by loading a save.
+    /// The camera is kept so the view doesn't jump.
+    pub fn reload_world(&mut self, app: &mut App) {
+        let astro_objs = query_astro_objs(app);
+        self.galaxy_view.selected_idx = 0;
+        self.galaxy_view.selected_astro_obj = astro_objs.first().copied();
+        self.galaxy_view.target_astro_obj = None;
+        self.galaxy_view.selected_ship = None;
+        self.galaxy_view.order_message = None;
+        self.galaxy_view.astro_objs = astro_objs;
+        // entities don't survive a reload, so a star system can't stay open
+        self.system_view = SystemView::new();
+        self.search = SearchState::new();
+        self.active_view = View::Galaxy;
+    }
+
+    /// Fit the views to a new terminal size.
+    pub fn resize(&mut self, terminal_size: Rect) {
+        let (view_area, _) = main_layout(terminal_size);
+        let (canvas_area, _) = galaxy_layout(view_area);
+        log::info!(
+            "resizing galaxy canvas to {}x{}",
+            canvas_area.width,
+            canvas_area.height
+        );
+        self.galaxy_view
+            .camera
+            .resize((canvas_area.width as f64, canvas_area.height as f64));
+    }
+
+    /// Open the search modal with an empty query listing every named object.
+    pub fn open_search(&mut self, app: &mut App) {
+        self.search = SearchState::new();
+        self.search.update_results(app);
+        self.active_modal = Modal::SearchObj;
+    }
 }
 
-pub fn ui<B: Backend>(f: &mut Frame<B>, tui_state: &mut TuiState, app: &mut App) {
+/// Split the terminal into the active view and the status bar along the bottom.
+pub fn main_layout(area: Rect) -> (Rect, Rect) {
+    let chunks = Layout::default()
+        .direction(layout::Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+        .split(area);
+    (chunks[0], chunks[1])
+}
 
+pub fn ui<B: Backend>(f: &mut Frame<B>, tui_state: &mut TuiState, app: &mut App) {
+    // the selected ship may have been destroyed since the last frame
+    let selected_ship = tui_state.galaxy_view.selected_ship;
+    if selected_ship.is_some_and(|ship| app.world.get_entity(ship).is_none()) {
+        tui_state.galaxy_view.selected_ship = None;
+    }
+    let (view_area, status_area) = main_layout(f.size());
     match tui_state.active_view {
-        View::Galaxy => draw_galaxy_view(f, tui_state, app),
+        View::Galaxy => draw_galaxy_view(f, view_area, tui_state, app),
+        View::System => draw_system_view(f, view_area, tui_state, app),
     }
+    draw_status_bar(f, status_area, app);
     match tui_state.active_modal {
         Modal::Help => draw_help_modal(f, tui_state, app),
+        Modal::SearchObj => draw_search_modal(f, tui_state),
+        Modal::CombatReport => draw_combat_report_modal(f, tui_state, app),
         _ => {}
     }
 }
 
+/// An object with its point on the canvas and the color to draw it in instead of the one
+/// for its kind, e.g. its owner's.
+pub type GalaxyPoint<'a> = (
+    &'a cmp::Location,
+    &'a cmp::astronomy::GalacticObj,
+    (f64, f64),
+    Option<Color>,
+);
+
 #[derive(Debug, Clone)]
 pub struct Points<'a> {
-    pub coords: &'a Vec<(&'a cmp::Location, (f64, f64))>,
-    pub color: Color,
+    pub coords: &'a Vec<GalaxyPoint<'a>>,
     pub selected_astro_obj: Option<(u32, u32)>,
 }
 
+/// Ships are printed over the braille dots, so they stand out from stars and can sit on one.
+const SHIP_MARKER: &str = "▲";
+
+/// Braille dots lit within an object's cell, as (column, row) with 2 columns and 4 rows.
+/// Stars are a single dot at their exact point instead.
+fn galactic_obj_glyph(galactic_obj: &cmp::astronomy::GalacticObj) -> &'static [(usize, usize)] {
+    match galactic_obj {
+        cmp::astronomy::GalacticObj::Star => &[],
+        cmp::astronomy::GalacticObj::BlackHole => &[(0, 1), (1, 1), (0, 2), (1, 2)],
+        cmp::astronomy::GalacticObj::SupermassiveBlackHole => &[
+            (0, 0),
+            (1, 0),
+            (0, 1),
+            (1, 1),
+            (0, 2),
+            (1, 2),
+            (0, 3),
+            (1, 3),
+        ],
+        cmp::astronomy::GalacticObj::Nebula => &[(0, 0), (1, 1), (0, 2), (1, 3)],
+    }
+}
+
+fn player_color(color: cmp::PlayerColor) -> Color {
+    match color {
+        cmp::PlayerColor::Blue => Color::Blue,
+        cmp::PlayerColor::Green => Color::Green,
+        cmp::PlayerColor::White => Color::White,
+        cmp::PlayerColor::LightBlue => Color::LightBlue,
+        cmp::PlayerColor::LightGreen => Color::LightGreen,
+        cmp::PlayerColor::Gray => Color::Gray,
+    }
+}
+
+fn galactic_obj_color(galactic_obj: &cmp::astronomy::GalacticObj) -> Color {
+    match galactic_obj {
+        cmp::astronomy::GalacticObj::Star => Color::Yellow,
+        cmp::astronomy::GalacticObj::BlackHole => Color::Magenta,
+        cmp::astronomy::GalacticObj::SupermassiveBlackHole => Color::LightMagenta,
+        cmp::astronomy::GalacticObj::Nebula => Color::Cyan,
+    }
+}
+
 impl<'a> Shape for Points<'a> {
     fn draw(&self, painter: &mut Painter) {
         let astro_obj_selected = self.selected_astro_obj.is_some();
-        for (loc, point) in self.coords {
-            let mut color = self.color;
+        for (loc, galactic_obj, point, color_override) in self.coords {
+            let mut color = color_override.unwrap_or_else(|| galactic_obj_color(galactic_obj));
             if astro_obj_selected && self.selected_astro_obj.unwrap() == (loc.x, loc.y) {
                 log::trace!("selected astro obj at ({}, {})", loc.x, loc.y);
                 color = Color::Red;
             }
             if let Some((x, y)) = painter.get_point(point.0, point.1) {
-                painter.paint(x, y, color);
+                let glyph = galactic_obj_glyph(galactic_obj);
+                if glyph.is_empty() {
+                    painter.paint(x, y, color);
+                }
+                // snap to the braille cell so the glyph never spills into a neighbour
+                let (cell_x, cell_y) = (x - x % 2, y - y % 4);
+                for (dx, dy) in glyph {
+                    painter.paint(cell_x + dx, cell_y + dy, color);
+                }
             }
         }
     }
@@ -189,34 +927,506 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: layout::Rect) -> layout::Rec
         .split(popup_layout[1])[1]
 }
 
-fn draw_galaxy_view<B: Backend>(f: &mut Frame<B>, tui_state: &mut TuiState, app: &mut App) {
-    let mut galactic_obj_query = app
+/// Width of the info panel to the right of the galaxy and system canvases.
+const INFO_PANEL_WIDTH: u16 = 32;
+
+/// Split the galaxy or system view into the canvas and the info panel beside it.
+pub fn galaxy_layout(area: Rect) -> (Rect, Rect) {
+    let chunks = Layout::default()
+        .direction(layout::Direction::Horizontal)
+        .constraints([Constraint::Min(0), Constraint::Length(INFO_PANEL_WIDTH)].as_ref())
+        .split(area);
+    (chunks[0], chunks[1])
+}
+
+fn galactic_obj_label(galactic_obj: cmp::astronomy::GalacticObj) -> &'static str {
+    match galactic_obj {
+        cmp::astronomy::GalacticObj::Star => "Star",
+        cmp::astronomy::GalacticObj::BlackHole => "Black hole",
+        cmp::astronomy::GalacticObj::SupermassiveBlackHole => "Supermassive black hole",
+        cmp::astronomy::GalacticObj::Nebula => "Nebula",
+    }
+}
+
+fn stellar_obj_label(stellar_obj: cmp::astronomy::StellarObj) -> &'static str {
+    match stellar_obj {
+        cmp::astronomy::StellarObj::Planet => "planet",
+        cmp::astronomy::StellarObj::Moon => "moon",
+        cmp::astronomy::StellarObj::AsteroidBelt => "asteroid belt",
+    }
+}
+
+fn draw_info_panel<B: Backend>(f: &mut Frame<B>, area: Rect, tui_state: &TuiState, app: &mut App) {
+    let block = Block::default().borders(Borders::ALL).title("Info");
+    let info = tui_state
+        .galaxy_view
+        .selected_astro_obj
+        .and_then(|cell| query_obj_info(app, cell));
+    let Some(info) = info else {
+        f.render_widget(Paragraph::new("Nothing selected").block(block), area);
+        return;
+    };
+    let label = |text: &str| Span::styled(text.to_string(), Style::default().fg(Color::DarkGray));
+    let loc = &info.location;
+    let out_of_sight = fog::local_fog(&app.world)
+        .is_some_and(|fog| fog.visibility((loc.x, loc.y)) != fog::CellVisibility::Visible);
+    let mut text = vec![
+        Spans::from(Span::styled(
+            info.name,
+            Style::default().fg(galactic_obj_color(&info.galactic_obj)),
+        )),
+        Spans::from(vec![
+            label("Kind: "),
+            Span::raw(galactic_obj_label(info.galactic_obj)),
+        ]),
+        Spans::from(vec![
+            label("Location: "),
+            Span::raw(format!("{}, {}, {}, {}", loc.x, loc.y, loc.z, loc.w)),
+        ]),
+        Spans::from(vec![
+            label("Owner: "),
+            match &info.owner {
+                _ if out_of_sight => Span::raw("unknown"),
+                Some(owner) => Span::styled(
+                    owner.name.clone(),
+                    Style::default().fg(player_color(owner.color)),
+                ),
+                None => Span::raw("none"),
+            },
+        ]),
+    ];
+    if out_of_sight {
+        text.push(Spans::from(label("Out of sensor range")));
+    }
+    if !info.bodies.is_empty() {
+        text.push(Spans::from(""));
+        text.push(Spans::from(label("Orbiting:")));
+    }
+    for body in &info.bodies {
+        // moons are listed under their planet
+        let indent = if body.moon_slot == 0 { " " } else { "   " };
+        text.push(Spans::from(vec![
+            Span::styled(
+                format!("{}{}", indent, body.name),
+                Style::default().fg(planet_color(body.planet_class)),
+            ),
+            label(&format!(" {}", stellar_obj_label(body.stellar_obj))),
+        ]));
+    }
+    let pipelines: Vec<PipelineInfo> = query_pipelines(app)
+        .into_iter()
+        .filter(|pipeline| {
+            let cell = (loc.x, loc.y);
+            pipeline.from_cell == cell || pipeline.to_cell == cell
+        })
+        .collect();
+    if !pipelines.is_empty() {
+        text.push(Spans::from(""));
+        text.push(Spans::from(label("Pipelines:")));
+    }
+    for pipeline in pipelines {
+        let link = &pipeline.link;
+        text.push(Spans::from(vec![
+            Span::raw(format!(" {} > {} ", pipeline.from_name, pipeline.to_name)),
+            if link.disrupted {
+                Span::styled("disrupted", Style::default().fg(Color::Red))
+            } else {
+                Span::raw(format!("{}/{} per tick", link.flow, link.capacity))
+            },
+        ]));
+    }
+    text.extend(ship_info_lines(tui_state, app));
+    // order messages are longer than the panel is wide
+    f.render_widget(
+        Paragraph::new(text).block(block).wrap(Wrap { trim: false }),
+        area,
+    );
+}
+
+pub fn cargo_label(kind: cmp::Cargo) -> &'static str {
+    match kind {
+        cmp::Cargo::Resources => "resources",
+        cmp::Cargo::Colonists => "colonists",
+        cmp::Cargo::Probes => "probes",
+    }
+}
+
+pub fn structure_label(structure: cmp::Structure) -> &'static str {
+    match structure {
+        cmp::Structure::Pipeline => "pipeline",
+        cmp::Structure::ResearchLab => "research lab",
+        cmp::Structure::Factory => "factory",
+        cmp::Structure::SpacePort => "space port",
+        cmp::Structure::Radar => "radar",
+        cmp::Structure::SignalJammer => "signal jammer",
+    }
+}
+
+/// One indented line per kind of cargo there is any of.
+fn cargo_lines(contents: &BTreeMap<cmp::Cargo, u32>) -> Vec<Spans<'static>> {
+    contents
+        .iter()
+        .filter(|(_, &amount)| amount > 0)
+        .map(|(&kind, amount)| Spans::from(format!("  {} {}", amount, cargo_label(kind))))
+        .collect()
+}
+
+/// The selected ship's part of the info panel, followed by what became of its last order.
+fn ship_info_lines(tui_state: &TuiState, app: &App) -> Vec<Spans<'static>> {
+    let label = |text: &str| Span::styled(text.to_string(), Style::default().fg(Color::DarkGray));
+    let mut text = vec![];
+    let ship = tui_state
+        .galaxy_view
+        .selected_ship
+        .and_then(|ship| query_ship_info(app, ship));
+    if let Some(ship) = ship {
+        let color = ship
+            .owner
+            .as_ref()
+            .map_or(Color::White, |owner| player_color(owner.color));
+        text.push(Spans::from(""));
+        text.push(Spans::from(vec![
+            label("Ship: "),
+            Span::styled(ship.name, Style::default().fg(color)),
+        ]));
+        text.push(Spans::from(vec![
+            label("At: "),
+            Span::raw(format!("{}, {}", ship.cell.0, ship.cell.1)),
+        ]));
+        text.push(Spans::from(vec![
+            label("Heading to: "),
+            match ship.destination {
+                Some((star, cells)) => Span::raw(format!("{} ({} cells)", star, cells)),
+                None => Span::raw("nowhere"),
+            },
+        ]));
+        if let Some(engine) = &ship.engine {
+            text.push(Spans::from(vec![
+                label("Fuel: "),
+                Span::raw(format!(
+                    "{}/{}, {} cells",
+                    engine.current_fuel,
+                    engine.max_fuel,
+                    ships::range(engine)
+                )),
+            ]));
+        }
+        if let (Some(hull), Some(shields)) = (ship.hull, ship.shields) {
+            text.push(Spans::from(vec![
+                label("Hull: "),
+                Span::raw(format!("{}/{}", hull.current, hull.max)),
+                label(" shields: "),
+                Span::raw(format!("{}/{}", shields.current, shields.max)),
+            ]));
+        }
+        if ship.disabled {
+            text.push(Spans::from(Span::styled(
+                "Disabled",
+                Style::default().fg(Color::Red),
+            )));
+        }
+        if let Some(cargo_bay) = &ship.cargo_bay {
+            text.push(Spans::from(vec![
+                label("Hold: "),
+                Span::raw(format!(
+                    "{}/{}",
+                    cargo_bay.current_cargo(),
+                    cargo_bay.max_cargo
+                )),
+            ]));
+            text.extend(cargo_lines(&cargo_bay.contents));
+        }
+        if !ship.trade_route.is_empty() {
+            text.push(Spans::from(vec![
+                label("Route: "),
+                Span::raw(ship.trade_route.join(" > ")),
+            ]));
+        }
+    }
+    if let Some(message) = &tui_state.galaxy_view.order_message {
+        text.push(Spans::from(Span::styled(
+            message.clone(),
+            Style::default().fg(Color::Yellow),
+        )));
+    }
+    text
+}
+
+/// What the system view shows beside its canvas: the selected body's owner and stockpile,
+/// and the selected ship with the cargo orders it can be given.
+fn draw_system_panel<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    tui_state: &TuiState,
+    app: &App,
+    body: Option<&SystemBody>,
+) {
+    let block = Block::default().borders(Borders::ALL).title("Info");
+    let label = |text: &str| Span::styled(text.to_string(), Style::default().fg(Color::DarkGray));
+    let mut text = vec![];
+    if let Some(body) = body {
+        text.push(Spans::from(Span::styled(
+            body.name.clone(),
+            Style::default().fg(planet_color(body.planet_class)),
+        )));
+        let owner_entity = app
+            .world
+            .get::<cmp::Owner>(body.entity)
+            .map(|owner| owner.0);
+        let local_player = app
+            .world
+            .get_resource::<resources::LocalPlayer>()
+            .map(|player| player.0);
+        // what's on someone else's body out of sensor range, and whose it is, can't be known
+        let out_of_sight = owner_entity.is_none_or(|owner| Some(owner) != local_player)
+            && fog::local_fog(&app.world).is_some_and(|fog| {
+                app.world
+                    .get::<cmp::Location>(body.entity)
+                    .is_some_and(|loc| !fog.visible.contains(&(loc.x, loc.y)))
+            });
+        let owner = owner_entity.and_then(|owner| app.world.get::<cmp::Player>(owner));
+        text.push(Spans::from(vec![
+            label("Owner: "),
+            match owner {
+                _ if out_of_sight => Span::raw("unknown"),
+                Some(owner) => Span::styled(
+                    owner.name.clone(),
+                    Style::default().fg(player_color(owner.color)),
+                ),
+                None => Span::raw("none"),
+            },
+        ]));
+        if !out_of_sight {
+            if let Some(stockpile) = app.world.get::<cmp::Stockpile>(body.entity) {
+                text.push(Spans::from(label("Stockpile:")));
+                text.extend(cargo_lines(&stockpile.0));
+            }
+            let structures = construction::structures_on(&app.world, body.entity);
+            if !structures.is_empty() {
+                text.push(Spans::from(label("Structures:")));
+                text.extend(structures.into_iter().map(|(structure, count)| {
+                    Spans::from(format!("  {} {}", count, structure_label(structure)))
+                }));
+            }
+            let queue = app
+                .world
+                .get::<cmp::structure::ConstructionQueue>(body.entity)
+                .filter(|queue| !queue.0.is_empty());
+            if let Some(queue) = queue {
+                text.push(Spans::from(label("Construction:")));
+                text.extend(queue.0.iter().map(|order| {
+                    Spans::from(format!(
+                        "  {} ({} ticks)",
+                        structure_label(order.structure),
+                        order.remaining
+                    ))
+                }));
+            }
+        }
+    }
+    text.extend(ship_info_lines(tui_state, app));
+    let system_view = &tui_state.system_view;
+    if !system_view.route_stops.is_empty() {
+        let names: Vec<String> = system_view
+            .route_stops
+            .iter()
+            .map(|&planet| {
+                app.world
+                    .get::<cmp::Name>(planet)
+                    .map(|name| name.0.clone())
+                    .unwrap_or_default()
+            })
+            .collect();
+        text.push(Spans::from(vec![
+            label("New route: "),
+            Span::raw(names.join(" > ")),
+        ]));
+    }
+    if let Some(from) = system_view.pipeline_from {
+        let name = app
+            .world
+            .get::<cmp::Name>(from)
+            .map(|name| name.0.clone())
+            .unwrap_or_default();
+        text.push(Spans::from(vec![
+            label("New pipeline: "),
+            Span::raw(format!("{} > ?", name)),
+        ]));
+    }
+    text.push(Spans::from(""));
+    text.push(Spans::from(vec![
+        label("Cargo: "),
+        Span::raw(cargo_label(system_view.cargo_kind)),
+        label(" (c: change)"),
+    ]));
+    text.push(Spans::from(label("l/u: load/unload, t: add stop")));
+    let spec = construction::build_spec(system_view.structure_kind);
+    text.push(Spans::from(vec![
+        label("Build: "),
+        Span::raw(format!(
+            "{} for {} resources, {} ticks",
+            structure_label(system_view.structure_kind),
+            spec.cost,
+            spec.time
+        )),
+        label(" (p: change, b: build)"),
+    ]));
+    text.push(Spans::from(label("x: connect a pipeline")));
+    f.render_widget(
+        Paragraph::new(text).block(block).wrap(Wrap { trim: false }),
+        area,
+    );
+}
+
+/// Lay out name labels beside their objects on a canvas where one unit is one terminal
+/// cell. Each label is `(text, anchor point, always shown)`. A label is tried to the right
+/// and left of its anchor, then on the rows above and below on either side, and dropped if
+/// every spot runs off the frame or touches another label or object, so zooming in reveals
+/// more of them. Labels that are always shown are placed first and fall back to the first
+/// spot on screen if none is free. Returns the index of every placed label with the point
+/// its text starts at.
+fn place_labels(
+    labels: &[(String, (f64, f64), bool)],
+    obstacles: &[(f64, f64)],
+    frame_size: (f64, f64),
+) -> Vec<(usize, (f64, f64))> {
+    let cell = |(x, y): (f64, f64)| (x.floor() as i64, y.floor() as i64);
+    let (width, height) = (frame_size.0 as i64, frame_size.1 as i64);
+    let obstacles: HashSet<(i64, i64)> = obstacles.iter().map(|&point| cell(point)).collect();
+    let mut taken: HashSet<(i64, i64)> = HashSet::new();
+
+    let mut order: Vec<usize> = (0..labels.len()).collect();
+    order.sort_by_key(|&idx| !labels[idx].2);
+    let mut placed = Vec::new();
+    for idx in order {
+        let (text, anchor, always) = &labels[idx];
+        let len = text.chars().count() as i64;
+        let (x, y) = cell(*anchor);
+        let candidates = [
+            (x + 1, y),
+            (x - len, y),
+            (x + 1, y + 1),
+            (x + 1, y - 1),
+            (x - len, y + 1),
+            (x - len, y - 1),
+        ];
+        let on_screen =
+            |(lx, ly): (i64, i64)| lx >= 0 && lx + len <= width && ly >= 0 && ly < height;
+        // keep a blank cell either side so neighbouring labels don't run together
+        let is_free = |(lx, ly): (i64, i64)| {
+            (lx..lx + len).all(|cx| !obstacles.contains(&(cx, ly)))
+                && (lx - 1..=lx + len).all(|cx| !taken.contains(&(cx, ly)))
+        };
+        let spot = candidates
+            .into_iter()
+            .find(|&spot| on_screen(spot) && is_free(spot))
+            .or_else(|| {
+                always
+                    .then(|| candidates.into_iter().find(|&spot| on_screen(spot)))
+                    .flatten()
+            });
+        if let Some((lx, ly)) = spot {
+            taken.extend((lx..lx + len).map(|cx| (cx, ly)));
+            placed.push((idx, (lx as f64, ly as f64)));
+        }
+    }
+    placed
+}
+
+fn draw_status_bar<B: Backend>(f: &mut Frame<B>, area: Rect, app: &mut App) {
+    let Some(config) = app.world.get_resource::<resources::Config>() else {
+        return;
+    };
+    let label = |text: &str| Span::styled(text.to_string(), Style::default().fg(Color::DarkGray));
+    let spans = match config.mode {
+        clock::GameMode::RealTime => vec![
+            Span::raw("Real-time"),
+            label(" | tick "),
+            Span::raw(
+                app.world
+                    .get_resource::<resources::Tick>()
+                    .map_or(0, |tick| tick.0)
+                    .to_string(),
+            ),
+            label(" | "),
+            Span::raw(
+                app.world
+                    .get_resource::<resources::GameSpeed>()
+                    .copied()
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
+            label(" | space: pause, 1/2/5: speed, H: help"),
+        ],
+        clock::GameMode::TurnBased => vec![
+            Span::raw("Turn-based"),
+            label(" | turn "),
+            Span::raw(
+                app.world
+                    .get_resource::<resources::TurnNumber>()
+                    .map_or(0, |turn| turn.0)
+                    .to_string(),
+            ),
+            label(" | e: end turn, H: help"),
+        ],
+    };
+    let mut spans = spans;
+    let num_reports = query_combat_reports(app).len();
+    if num_reports > 0 {
+        spans.push(label(&format!(" | r: {} combat reports", num_reports)));
+    }
+    f.render_widget(Paragraph::new(Spans::from(spans)), area);
+}
+
+fn draw_galaxy_view<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    tui_state: &mut TuiState,
+    app: &mut App,
+) {
+    let (canvas_area, info_area) = galaxy_layout(area);
+    tui_state.galaxy_view.set_astro_objs(query_astro_objs(app));
+    let player_colors = query_player_colors(app);
+    let pipelines = query_pipelines(app);
+    let mut galactic_obj_query = app.world.query::<(
+        &cmp::astronomy::GalacticObj,
+        &cmp::Location,
+        Option<&cmp::Name>,
+        Option<&cmp::Owner>,
+    )>();
+    let mut ship_query = app.world.query_filtered::<(
+        Entity,
+        &cmp::Location,
+        Option<&cmp::Owner>,
+        Option<&cmp::ship::Course>,
+        Option<&cmp::ship::Disabled>,
+    ), With<cmp::ship::Engine>>();
+    let local_player = app
         .world
-        .query::<(&cmp::astronomy::GalacticObj, &cmp::Location)>();
+        .get_resource::<resources::LocalPlayer>()
+        .map(|player| player.0);
     let config = app
         .world
         .get_resource::<resources::Config>()
         .expect("config not found");
+    let fog = fog::local_fog(&app.world);
+    let selected_astro_obj = tui_state.galaxy_view.selected_astro_obj;
     let mut points = vec![];
-    for (_, loc) in galactic_obj_query.iter(&app.world) {
-        // Add linear interpolation to scale x, y (world coords + ui offset)
-        // from range [a,b] (the grid) to range [c,d] (the canvas)
+    let mut labels = vec![];
+    for (galactic_obj, loc, name, owner) in galactic_obj_query.iter(&app.world) {
+        let visibility = fog.map_or(fog::CellVisibility::Visible, |fog| {
+            fog.visibility((loc.x, loc.y))
+        });
+        if visibility == fog::CellVisibility::Unexplored {
+            continue;
+        }
+        // world coords + ui offset
         let x = loc.x as f64 + loc.ui_offset.0 as f64;
         let y = loc.y as f64 + loc.ui_offset.1 as f64;
-        let (a, b) = (0., config.galaxy_dimension as f64 - 1.);
-        let (cx, dx) = (
-            tui_state.galaxy_view.camera.origin.0 as f64,
-            tui_state.galaxy_view.camera.canvas_size.0 as f64
-                + tui_state.galaxy_view.camera.origin.0 as f64,
-        );
-        let (cy, dy) = (
-            tui_state.galaxy_view.camera.origin.1 as f64,
-            tui_state.galaxy_view.camera.canvas_size.1 as f64
-                + tui_state.galaxy_view.camera.origin.1 as f64,
-        );
-        let f_of_x = |p: f64| ((p - a) * ((dx - cx) / (b - a))) + cx;
-        let f_of_y = |p: f64| ((p - a) * ((dy - cy) / (b - a))) + cy;
-        let canvas_point = (f_of_x(x), f_of_y(y));
+        let canvas_point = tui_state
+            .galaxy_view
+            .camera
+            .grid_to_canvas((x, y), config.galaxy_dimension);
         log::trace!(
             "scaling astro_grid point ({}, {}) to canvas point ({}, {})",
             x,
@@ -224,51 +1434,907 @@ fn draw_galaxy_view<B: Backend>(f: &mut Frame<B>, tui_state: &mut TuiState, app:
             canvas_point.0,
             canvas_point.1
         );
-        points.push((loc, canvas_point));
+        // what's out of sight is dimmed, with no owner's color to give away who holds it
+        let color = match visibility {
+            fog::CellVisibility::Visible => {
+                owner.and_then(|owner| player_colors.get(&owner.0).copied())
+            }
+            _ => Some(Color::DarkGray),
+        };
+        points.push((loc, galactic_obj, canvas_point, color));
+        let is_selected = selected_astro_obj == Some((loc.x, loc.y));
+        if let (true, Some(name)) = (tui_state.galaxy_view.show_ids, name) {
+            if is_selected || *galactic_obj == cmp::astronomy::GalacticObj::Star {
+                labels.push((name.0.clone(), canvas_point, is_selected));
+            }
+        }
+    }
+    // pipelines run between star systems, so they're drawn from star to star
+    let pipelines: Vec<Line> = pipelines
+        .into_iter()
+        .map(|pipeline| {
+            let camera = &tui_state.galaxy_view.camera;
+            let point = |(x, y): (u32, u32)| {
+                camera.grid_to_canvas((x as f64, y as f64), config.galaxy_dimension)
+            };
+            let ((x1, y1), (x2, y2)) = (point(pipeline.from_cell), point(pipeline.to_cell));
+            let color = if pipeline.link.disrupted {
+                Color::Red
+            } else {
+                player_colors
+                    .get(&pipeline.owner)
+                    .copied()
+                    .unwrap_or(Color::White)
+            };
+            Line {
+                x1,
+                y1,
+                x2,
+                y2,
+                color,
+            }
+        })
+        .collect();
+    let selected_ship = tui_state.galaxy_view.selected_ship;
+    let mut ships = vec![];
+    let mut courses = vec![];
+    let mut reach = None;
+    for (entity, loc, owner, course, disabled) in ship_query.iter(&app.world) {
+        // other players' ships are only seen within sensor range, and where they're going
+        // not at all
+        let owned = local_player.is_none_or(|player| owner.is_some_and(|owner| owner.0 == player));
+        let in_sight = fog.is_none_or(|fog| fog.visible.contains(&(loc.x, loc.y)));
+        if !owned && !in_sight {
+            continue;
+        }
+        let camera = &tui_state.galaxy_view.camera;
+        let canvas_point =
+            camera.grid_to_canvas((loc.x as f64, loc.y as f64), config.galaxy_dimension);
+        let color = if selected_ship == Some(entity) {
+            Color::Red
+        } else if disabled.is_some() {
+            Color::DarkGray
+        } else {
+            owner
+                .and_then(|owner| player_colors.get(&owner.0).copied())
+                .unwrap_or(Color::White)
+        };
+        if selected_ship == Some(entity) {
+            reach = app.world.get::<cmp::ship::Engine>(entity).map(|engine| {
+                // ships cross diagonals as fast as straights, so the reachable area
+                // is a square
+                let reach = ships::range(engine) as f64;
+                let corner = |dx: f64, dy: f64| {
+                    camera.grid_to_canvas(
+                        (loc.x as f64 + dx, loc.y as f64 + dy),
+                        config.galaxy_dimension,
+                    )
+                };
+                let (a, b) = (corner(-reach, -reach), corner(reach, reach));
+                canvas::Rectangle {
+                    x: a.0.min(b.0),
+                    y: a.1.min(b.1),
+                    width: (a.0 - b.0).abs(),
+                    height: (a.1 - b.1).abs(),
+                    color: Color::DarkGray,
+                }
+            });
+        }
+        ships.push((canvas_point, color));
+        let destination = course
+            .filter(|_| owned)
+            .and_then(|course| app.world.get::<cmp::Location>(course.destination));
+        if let Some(dest) = destination {
+            let (x2, y2) = camera.grid_to_canvas(
+                (
+                    dest.x as f64 + dest.ui_offset.0 as f64,
+                    dest.y as f64 + dest.ui_offset.1 as f64,
+                ),
+                config.galaxy_dimension,
+            );
+            courses.push(Line {
+                x1: canvas_point.0,
+                y1: canvas_point.1,
+                x2,
+                y2,
+                color: Color::DarkGray,
+            });
+        }
     }
+    let obstacles: Vec<(f64, f64)> = points
+        .iter()
+        .map(|(_, _, point, _)| *point)
+        .chain(ships.iter().map(|(point, _)| *point))
+        .collect();
+    let frame_size = (canvas_area.width as f64, canvas_area.height as f64);
+    let placed = place_labels(&labels, &obstacles, frame_size);
     let canvas = Canvas::default()
         .block(Block::default().borders(Borders::ALL).title("Galaxy"))
         .marker(tui::symbols::Marker::Braille)
         .paint(|ctx| {
+            if let Some(reach) = &reach {
+                ctx.draw(reach);
+            }
+            for pipeline in &pipelines {
+                ctx.draw(pipeline);
+            }
+            for course in &courses {
+                ctx.draw(course);
+            }
             ctx.draw(&Points {
                 coords: &points,
-                color: Color::Yellow,
-                selected_astro_obj: tui_state.galaxy_view.selected_astro_obj,
+                selected_astro_obj,
             });
-            // ctx.draw(&Line {
-            //     x1: 0.,
-            //     y1: 10.,
-            //     y2: 10.,
-            //     x2: 0.,
-            //     color: Color::LightBlue,
-            // });
+            ctx.layer();
+            for &((x, y), color) in &ships {
+                ctx.print(x, y, Span::styled(SHIP_MARKER, Style::default().fg(color)));
+            }
+            for &(idx, (x, y)) in &placed {
+                let (name, _, is_selected) = &labels[idx];
+                let color = if *is_selected {
+                    Color::Red
+                } else {
+                    Color::Gray
+                };
+                ctx.print(x, y, Span::styled(name.clone(), Style::default().fg(color)));
+            }
+        })
+        .x_bounds([0., canvas_area.width as f64])
+        .y_bounds([0., canvas_area.height as f64]);
+
+    f.render_widget(canvas, canvas_area);
+    draw_info_panel(f, info_area, tui_state, app);
+}
+
+fn planet_color(planet_class: Option<cmp::astronomy::PlanetClass>) -> Color {
+    match planet_class {
+        Some(cmp::astronomy::PlanetClass::Barren) => Color::Gray,
+        Some(cmp::astronomy::PlanetClass::Desert) => Color::LightYellow,
+        Some(cmp::astronomy::PlanetClass::Rocky) => Color::LightRed,
+        Some(cmp::astronomy::PlanetClass::Ocean) => Color::LightBlue,
+        Some(cmp::astronomy::PlanetClass::Ice) => Color::White,
+        Some(cmp::astronomy::PlanetClass::GasGiant) => Color::LightGreen,
+        None => Color::DarkGray,
+    }
+}
+
+/// Spread bodies around their orbits so they don't all line up on one side of the star.
+fn orbit_angle(slot: u32) -> f64 {
+    // the golden angle never repeats, so neighbouring orbits never line up
+    slot as f64 * 2.399963
+}
+
+fn draw_system_view<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    tui_state: &mut TuiState,
+    app: &mut App,
+) {
+    let Some(star) = tui_state.system_view.star else {
+        tui_state.active_view = View::Galaxy;
+        return;
+    };
+    let star_name = app
+        .world
+        .get::<cmp::Name>(star)
+        .map(|name| name.0.clone())
+        .unwrap_or_default();
+    let (canvas_area, panel_area) = galaxy_layout(area);
+    let bodies = query_system_bodies(app, star);
+    let selected = bodies
+        .get(tui_state.system_view.selected_idx)
+        .map(|body| body.entity);
+    draw_system_panel(
+        f,
+        panel_area,
+        tui_state,
+        app,
+        bodies.get(tui_state.system_view.selected_idx),
+    );
+    let area = canvas_area;
+
+    // a terminal cell is about twice as tall as it is wide, so y gets twice the units
+    // to keep orbits round
+    let (width, height) = (area.width as f64, area.height as f64);
+    let max_radius = (width / 2.).min(height) - 4.;
+    let num_slots = bodies.iter().map(|body| body.slot).max().unwrap_or(1) as f64;
+    let orbit_radius = |slot: u32| max_radius * slot as f64 / num_slots;
+
+    let canvas = Canvas::default()
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("System: {}", star_name)),
+        )
+        .marker(tui::symbols::Marker::Braille)
+        .paint(|ctx| {
+            for body in bodies.iter().filter(|body| body.moon_slot == 0) {
+                // belts are a dense ring of rock, plain orbits a faint line
+                let radius = orbit_radius(body.slot);
+                let is_belt = body.stellar_obj == cmp::astronomy::StellarObj::AsteroidBelt;
+                let num_points = (radius * if is_belt { 8. } else { 3. }) as u32;
+                let coords: Vec<(f64, f64)> = (0..num_points)
+                    .map(|i| {
+                        let angle = i as f64 / num_points as f64 * std::f64::consts::TAU;
+                        (radius * angle.cos(), radius * angle.sin())
+                    })
+                    .collect();
+                let color = match (is_belt, selected == Some(body.entity)) {
+                    (true, true) => Color::Red,
+                    (true, false) => Color::Gray,
+                    (false, _) => Color::DarkGray,
+                };
+                ctx.draw(&canvas::Points {
+                    coords: &coords,
+                    color,
+                });
+            }
+            ctx.layer();
+            ctx.print(
+                0.,
+                0.,
+                Span::styled("*", Style::default().fg(Color::Yellow)),
+            );
+            for body in &bodies {
+                let planet_angle = orbit_angle(body.slot);
+                let radius = orbit_radius(body.slot);
+                let (mut x, mut y) = (radius * planet_angle.cos(), radius * planet_angle.sin());
+                let (glyph, label) = match body.stellar_obj {
+                    cmp::astronomy::StellarObj::Planet => ("o", body.name.clone()),
+                    cmp::astronomy::StellarObj::Moon => {
+                        let moon_angle = orbit_angle(body.moon_slot);
+                        x += 2. * body.moon_slot as f64 * moon_angle.cos();
+                        y += 2. * body.moon_slot as f64 * moon_angle.sin();
+                        (".", String::new())
+                    }
+                    // belts are drawn as rings above, only their label goes here
+                    cmp::astronomy::StellarObj::AsteroidBelt => ("", body.name.clone()),
+                };
+                let color = if selected == Some(body.entity) {
+                    Color::Red
+                } else {
+                    planet_color(body.planet_class)
+                };
+                let style = Style::default().fg(color);
+                ctx.print(x, y, Span::styled(glyph, style));
+                if !label.is_empty() {
+                    ctx.print(x + 2., y, Span::styled(label, style));
+                }
+            }
         })
-        .x_bounds([0., f.size().width as f64])
-        .y_bounds([0., f.size().height as f64]);
+        .x_bounds([-width / 2., width / 2.])
+        .y_bounds([-height, height]);
 
-    f.render_widget(canvas, f.size());
+    f.render_widget(canvas, area);
 }
 
 fn draw_help_modal<B: Backend>(f: &mut Frame<B>, tui_state: &TuiState, app: &mut App) {
     let block = Block::default().title("Help").borders(Borders::ALL);
-    let area = centered_rect(60, 20, f.size());
+    let area = centered_rect(60, 70, f.size());
     // add text to the area
     let text = vec![
-        Spans::from("Press 'q' to quit"),
-        Spans::from("Press 'h' to toggle this help menu"),
+        Spans::from("Press 'q' to quit (the game is autosaved)"),
+        Spans::from("Press 'H' to toggle this help menu"),
+        Spans::from("Tab/Shift-Tab cycle the selection, h/j/k/l select the nearest object"),
+        Spans::from("Press F5 to quicksave, F9 to load the latest save"),
+        Spans::from("Arrow keys pan, i/o zoom in and out around the selection"),
+        Spans::from("Click to select, drag to pan, scroll to zoom around the cursor"),
+        Spans::from("Space pauses, 1/2/5 set the game speed, 'e' ends a turn in turn mode"),
+        Spans::from("Press 'n' to toggle star names"),
+        Spans::from("Press 's' to select your next ship, 'g' to send it to the selected star"),
+        Spans::from("Hostile ships in the same place fight, 'r' opens the combat reports"),
+        Spans::from("The box around a selected ship is how far its fuel takes it"),
+        Spans::from("Ships refuel while docked at one of your space ports"),
+        Spans::from("Press '/' to search by name, Up/Down and Enter to jump to a match"),
+        Spans::from("Yellow: stars, magenta: black holes, cyan: nebulae"),
+        Spans::from("Stars owned by a player are drawn in that player's color"),
+        Spans::from("Gray objects are out of sensor range, and who owns them is unknown"),
+        Spans::from("Radars extend your sensors around their star, jammers shorten enemy ones"),
+        Spans::from("Ships are drawn as ▲, with a line to where they're headed"),
+        Spans::from("Press Enter to open the selected star system, Esc to go back"),
+        Spans::from("In a star system, Up/Down select a planet, moon or belt"),
+        Spans::from("There 'c' picks a cargo, 'l'/'u' load and unload the selected ship"),
+        Spans::from("'t' adds the planet to a trade route, 'T' clears the ship's route"),
+        Spans::from("'p' picks a structure and 'b' queues it on your selected planet"),
+        Spans::from("'x' on a planet with a pipeline, then on one elsewhere, connects them"),
     ];
     let paragraph = Paragraph::new(text.clone()).block(block);
     f.render_widget(Clear, area); //this clears out the background
     f.render_widget(paragraph, area);
 }
 
+fn outcome_label(outcome: combat::Outcome) -> (&'static str, Color) {
+    match outcome {
+        combat::Outcome::Survived => ("survived", Color::Green),
+        combat::Outcome::Disabled => ("disabled", Color::Yellow),
+        combat::Outcome::Destroyed => ("destroyed", Color::Red),
+    }
+}
+
+fn draw_combat_report_modal<B: Backend>(f: &mut Frame<B>, tui_state: &TuiState, app: &App) {
+    let reports = query_combat_reports(app);
+    let area = centered_rect(60, 50, f.size());
+    let label = |text: &str| Span::styled(text.to_string(), Style::default().fg(Color::DarkGray));
+    let idx = tui_state.report_idx.min(reports.len().saturating_sub(1));
+    let title = format!("Combat report {}/{}", idx + 1, reports.len());
+    let mut text = vec![];
+    match reports.get(idx) {
+        Some(report) => {
+            let when = match app
+                .world
+                .get_resource::<resources::Config>()
+                .map(|c| c.mode)
+            {
+                Some(clock::GameMode::TurnBased) => format!("turn {}", report.turn),
+                _ => format!("tick {}", report.tick),
+            };
+            text.push(Spans::from(format!(
+                "Battle at {}, {} on {}, {} rounds",
+                report.cell.0, report.cell.1, when, report.rounds
+            )));
+            text.push(Spans::from(""));
+            for ship in &report.ships {
+                let (outcome, color) = outcome_label(ship.outcome);
+                text.push(Spans::from(vec![
+                    Span::raw(ship.name.clone()),
+                    label(&format!(" ({})", ship.owner_name)),
+                    Span::raw(" "),
+                    Span::styled(outcome, Style::default().fg(color)),
+                    label(&format!(", hull {}/{}", ship.hull.current, ship.hull.max)),
+                ]));
+            }
+        }
+        None => text.push(Spans::from("No battles yet")),
+    }
+    text.push(Spans::from(""));
+    text.push(Spans::from(label("Left/Right: older/newer, Esc: close")));
+    let block = Block::default().title(title).borders(Borders::ALL);
+    f.render_widget(Clear, area);
+    f.render_widget(
+        Paragraph::new(text).block(block).wrap(Wrap { trim: false }),
+        area,
+    );
+}
+
+fn draw_search_modal<B: Backend>(f: &mut Frame<B>, tui_state: &TuiState) {
+    let area = centered_rect(60, 60, f.size());
+    let chunks = Layout::default()
+        .direction(layout::Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+        .split(area);
+    let search = &tui_state.search;
+    let items: Vec<ListItem> = search
+        .results
+        .iter()
+        .map(|result| {
+            ListItem::new(Spans::from(vec![
+                Span::raw(result.name.clone()),
+                Span::styled(
+                    format!("  ({}, {})", result.cell.0, result.cell.1),
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ]))
+        })
+        .collect();
+    let title = if search.num_matches > search.results.len() {
+        format!(
+            "{} matches, showing the best {}",
+            search.num_matches,
+            search.results.len()
+        )
+    } else {
+        format!("{} matches", search.num_matches)
+    };
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().fg(Color::Red))
+        .highlight_symbol("> ");
+    let mut list_state = ListState::default();
+    list_state.select(search.selected().map(|_| search.selected_idx));
+
+    f.render_widget(Clear, area);
+    f.render_widget(search.textarea.widget(), chunks[0]);
+    f.render_stateful_widget(list, chunks[1], &mut list_state);
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     #[test]
     fn test_canvas_camera() {
-        let mut camera = CanvasCamera::new((100., 100.));
+        let camera = CanvasCamera::new((100., 100.));
         assert_eq!(camera.frame_size, camera.canvas_size);
     }
+
+    fn screen_cell(point: (f64, f64)) -> (i64, i64) {
+        (point.0.floor() as i64, point.1.floor() as i64)
+    }
+
+    #[test]
+    fn test_zoom_keeps_anchor_in_place() {
+        let mut camera = CanvasCamera::new((100., 50.));
+        camera.origin = (-7., 3.);
+        camera.update();
+        let world_point = (17., 5.);
+        let anchor = camera.grid_to_canvas(world_point, 25);
+        for _ in 0..6 {
+            camera.zoom_in(anchor);
+            assert_eq!(
+                screen_cell(camera.grid_to_canvas(world_point, 25)),
+                screen_cell(anchor)
+            );
+        }
+        for _ in 0..12 {
+            camera.zoom_out(anchor);
+            assert_eq!(
+                screen_cell(camera.grid_to_canvas(world_point, 25)),
+                screen_cell(anchor)
+            );
+        }
+    }
+
+    #[test]
+    fn test_zoom_is_clamped() {
+        let mut camera = CanvasCamera::new((100., 50.));
+        for _ in 0..50 {
+            camera.zoom_in(camera.frame_center);
+        }
+        assert_eq!(camera.scale, camera.max_scale);
+        assert_eq!(
+            camera.canvas_size,
+            (100. * camera.max_scale, 50. * camera.max_scale)
+        );
+        for _ in 0..50 {
+            camera.zoom_out(camera.frame_center);
+        }
+        assert_eq!(camera.scale, camera.min_scale);
+    }
+
+    #[test]
+    fn test_resize_keeps_center() {
+        let mut camera = CanvasCamera::new((100., 50.));
+        camera.origin = (-40., 7.);
+        camera.zoom_in((30., 30.));
+        let center = camera.canvas_to_grid(camera.frame_center, 25);
+        camera.resize((61., 83.));
+        assert_eq!(camera.frame_center, (30.5, 41.5));
+        assert_eq!(camera.canvas_size, (61. * camera.scale, 83. * camera.scale));
+        let (x, y) = camera.canvas_to_grid(camera.frame_center, 25);
+        assert!((x - center.0).abs() < 1e-9 && (y - center.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_canvas_to_grid() {
+        let mut camera = CanvasCamera::new((100., 50.));
+        camera.origin = (-30., 12.);
+        camera.zoom_in((40., 20.));
+        let grid_point = camera.canvas_to_grid(camera.grid_to_canvas((3., 21.), 25), 25);
+        assert!((grid_point.0 - 3.).abs() < 1e-9 && (grid_point.1 - 21.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_screen_to_canvas() {
+        let camera = CanvasCamera::new((42., 12.));
+        // the border isn't part of the canvas
+        assert_eq!(camera.screen_to_canvas((0, 5)), None);
+        assert_eq!(camera.screen_to_canvas((41, 5)), None);
+        assert_eq!(camera.screen_to_canvas((5, 11)), None);
+        // the top left cell is near the top left of the canvas, the bottom right one near
+        // the bottom right
+        let (x, y) = camera.screen_to_canvas((1, 1)).unwrap();
+        assert!(x < 1. && y > 11.);
+        let (x, y) = camera.screen_to_canvas((40, 10)).unwrap();
+        assert!(x > 41. && y < 1.);
+    }
+
+    #[test]
+    fn test_pan_tiny_frame() {
+        // a terminal so narrow the frame is all border
+        let mut camera = CanvasCamera::new((2., 12.));
+        let origin = camera.origin;
+        camera.pan_by_cells(3, 1);
+        assert_eq!(camera.origin, origin);
+        assert_eq!(camera.screen_to_canvas((1, 1)), None);
+    }
+
+    #[test]
+    fn test_click_selects_drawn_star() {
+        // paint a star, find which terminal cell it landed in and click that cell
+        let mut app = App::new();
+        app.init_resource::<resources::Config>();
+        for (x, y) in [(3, 20), (17, 6), (18, 6)] {
+            app.world
+                .spawn((cmp::astronomy::GalacticObj::Star, cmp::Location::at(x, y)));
+        }
+        let mut tui_state = TuiState::new(&mut app);
+        let backend = tui::backend::TestBackend::new(82, 30);
+        let mut terminal = tui::Terminal::new(backend).unwrap();
+        tui_state.resize(Rect::new(0, 0, 82, 30));
+        let (canvas_area, _) = galaxy_layout(main_layout(Rect::new(0, 0, 82, 30)).0);
+        tui_state.galaxy_view.select_cell((3, 20));
+        terminal.draw(|f| ui(f, &mut tui_state, &mut app)).unwrap();
+
+        let buffer = terminal.backend().buffer();
+        let star_cell = (1..canvas_area.width - 1)
+            .flat_map(|column| (1..canvas_area.height - 1).map(move |row| (column, row)))
+            .find(|&(column, row)| buffer.get(column, row).fg == Color::Red)
+            .unwrap();
+        tui_state.galaxy_view.select_cell((17, 6));
+        let camera = &tui_state.galaxy_view.camera;
+        let grid_point = camera.canvas_to_grid(camera.screen_to_canvas(star_cell).unwrap(), 25);
+        tui_state.galaxy_view.select_nearest(grid_point);
+        assert_eq!(tui_state.galaxy_view.selected_astro_obj, Some((3, 20)));
+    }
+
+    #[test]
+    fn test_zoom_anchor() {
+        let mut galaxy_view = galaxy_view(vec![(12, 12), (0, 0)]);
+        let center = galaxy_view.camera.grid_to_canvas((12., 12.), 25);
+        assert_eq!(galaxy_view.zoom_anchor(25), center);
+        // once the selection is panned off screen zooming falls back to the frame center
+        galaxy_view.camera.origin = (-500., 0.);
+        galaxy_view.camera.update();
+        assert_eq!(galaxy_view.zoom_anchor(25), galaxy_view.camera.frame_center);
+    }
+
+    fn galaxy_view(astro_objs: Vec<(u32, u32)>) -> GalaxyView {
+        let mut galaxy_view = GalaxyView::new((100., 50.));
+        galaxy_view.astro_objs = astro_objs;
+        galaxy_view.select(0);
+        galaxy_view
+    }
+
+    #[test]
+    fn test_select_next_prev_wraps() {
+        let mut galaxy_view = galaxy_view(vec![(0, 0), (1, 1), (2, 2)]);
+        galaxy_view.select_prev();
+        assert_eq!(galaxy_view.selected_astro_obj, Some((2, 2)));
+        galaxy_view.select_next();
+        assert_eq!(galaxy_view.selected_astro_obj, Some((0, 0)));
+        galaxy_view.select_next();
+        assert_eq!(galaxy_view.selected_idx, 1);
+    }
+
+    #[test]
+    fn test_select_direction() {
+        let mut galaxy_view = galaxy_view(vec![(5, 5), (9, 5), (7, 8), (5, 1), (2, 6)]);
+        galaxy_view.select_direction((1, 0));
+        assert_eq!(galaxy_view.selected_astro_obj, Some((9, 5)));
+        // (5, 5) is further left than (7, 8) but straight ahead
+        galaxy_view.select_direction((-1, 0));
+        assert_eq!(galaxy_view.selected_astro_obj, Some((5, 5)));
+        galaxy_view.select_direction((0, -1));
+        assert_eq!(galaxy_view.selected_astro_obj, Some((5, 1)));
+        // nothing further down, so the selection stays put
+        galaxy_view.select_direction((0, -1));
+        assert_eq!(galaxy_view.selected_astro_obj, Some((5, 1)));
+        galaxy_view.select_direction((0, 1));
+        assert_eq!(galaxy_view.selected_astro_obj, Some((5, 5)));
+    }
+
+    #[test]
+    fn test_pan_to_selected() {
+        let mut galaxy_view = galaxy_view(vec![(0, 0), (24, 24)]);
+        galaxy_view.camera.scale = 3.;
+        galaxy_view.camera.update();
+        galaxy_view.select(1);
+        galaxy_view.pan_to_selected(25);
+        let (x, y) = galaxy_view.camera.grid_to_canvas((24., 24.), 25);
+        assert!((4. ..=96.).contains(&x), "x = {}", x);
+        assert!((4. ..=46.).contains(&y), "y = {}", y);
+    }
+
+    #[test]
+    fn test_place_labels() {
+        let label = |text: &str, anchor, always| (text.to_string(), anchor, always);
+        // the first label would cover the second star, so it moves above
+        let labels = vec![
+            label("Sirius", (2.5, 5.5), false),
+            label("Vega", (8.5, 5.5), false),
+        ];
+        let obstacles = vec![(2.5, 5.5), (8.5, 5.5)];
+        let placed = place_labels(&labels, &obstacles, (40., 20.));
+        assert_eq!(placed, vec![(0, (3., 6.)), (1, (9., 5.))]);
+
+        // crowded together only one fits, and the one that must be shown wins
+        let obstacles = vec![(5.5, 0.5), (6.5, 0.5)];
+        let labels = vec![
+            label("Sol", (5.5, 0.5), false),
+            label("Vega", (6.5, 0.5), false),
+        ];
+        assert_eq!(
+            place_labels(&labels, &obstacles, (9., 1.)),
+            vec![(0, (2., 0.))]
+        );
+        let labels = vec![
+            label("Sol", (5.5, 0.5), false),
+            label("Vega", (6.5, 0.5), true),
+        ];
+        assert_eq!(
+            place_labels(&labels, &obstacles, (9., 1.)),
+            vec![(1, (2., 0.))]
+        );
+
+        // labels never run off the frame
+        let labels = vec![label("Sirius", (1.5, 1.5), false)];
+        assert!(place_labels(&labels, &[(1.5, 1.5)], (5., 3.)).is_empty());
+    }
+
+    /// Draw the whole UI on a `width` x `height` terminal and return its text.
+    fn render(app: &mut App, tui_state: &mut TuiState, width: u16, height: u16) -> String {
+        let backend = tui::backend::TestBackend::new(width, height);
+        let mut terminal = tui::Terminal::new(backend).unwrap();
+        terminal.draw(|f| ui(f, tui_state, app)).unwrap();
+        terminal
+            .backend()
+            .buffer()
+            .content
+            .iter()
+            .map(|cell| cell.symbol.clone())
+            .collect()
+    }
+
+    fn app_with_system() -> (App, Entity) {
+        use bevy::prelude::BuildWorldChildren;
+
+        let mut app = App::new();
+        let loc = |z, w| cmp::Location {
+            z,
+            w,
+            ..cmp::Location::at(1, 1)
+        };
+        let star = app
+            .world
+            .spawn((
+                cmp::astronomy::GalacticObj::Star,
+                loc(0, 0),
+                cmp::Name("Sol".to_string()),
+            ))
+            .id();
+        let moon = app
+            .world
+            .spawn((
+                cmp::astronomy::StellarObj::Moon,
+                loc(1, 1),
+                cmp::Name("Sol I b".to_string()),
+            ))
+            .id();
+        let planet = app
+            .world
+            .spawn((
+                cmp::astronomy::StellarObj::Planet,
+                cmp::astronomy::PlanetClass::Ocean,
+                loc(1, 0),
+                cmp::Name("Sol I".to_string()),
+            ))
+            .push_children(&[moon])
+            .id();
+        let belt = app
+            .world
+            .spawn((
+                cmp::astronomy::StellarObj::AsteroidBelt,
+                loc(2, 0),
+                cmp::Name("Sol Belt II".to_string()),
+            ))
+            .id();
+        app.world.entity_mut(star).push_children(&[belt, planet]);
+        (app, star)
+    }
+
+    #[test]
+    fn test_query_system_bodies() {
+        let (app, star) = app_with_system();
+        let names: Vec<String> = query_system_bodies(&app, star)
+            .into_iter()
+            .map(|body| body.name)
+            .collect();
+        assert_eq!(names, vec!["Sol I", "Sol I b", "Sol Belt II"]);
+    }
+
+    #[test]
+    fn test_info_panel() {
+        let (mut app, star) = app_with_system();
+        app.init_resource::<resources::Config>();
+        let player = app
+            .world
+            .spawn(cmp::Player {
+                name: "Player 1".to_string(),
+                color: cmp::PlayerColor::Blue,
+                homeworld: None,
+            })
+            .id();
+        app.world.entity_mut(star).insert(cmp::Owner(player));
+        let mut tui_state = TuiState::new(&mut app);
+        let info = query_obj_info(&mut app, (1, 1)).unwrap();
+        assert_eq!(info.name, "Sol");
+        assert_eq!(info.galactic_obj, cmp::astronomy::GalacticObj::Star);
+        assert_eq!(info.bodies.len(), 3);
+        assert!(query_obj_info(&mut app, (0, 0)).is_none());
+
+        let rendered = render(&mut app, &mut tui_state, 80, 20);
+        assert!(rendered.contains("Kind: Star"));
+        assert!(rendered.contains("Location: 1, 1, 0, 0"));
+        assert!(rendered.contains("Owner: Player 1"));
+        assert!(rendered.contains("Sol Belt II asteroid belt"));
+        assert!(rendered.contains("Real-time | tick 0 | 1x"));
+    }
+
+    #[test]
+    fn test_draw_ship_and_course() {
+        let (mut app, star) = app_with_system();
+        app.init_resource::<resources::Config>();
+        let ship = app
+            .world
+            .spawn((crate::ships::scout((3, 3)), cmp::Name("Scout".to_string())))
+            .id();
+        assert!(crate::ships::set_course(&mut app.world, ship, star).is_ok());
+        let mut tui_state = TuiState::new(&mut app);
+        assert_eq!(query_local_ships(&mut app), vec![ship]);
+        tui_state.galaxy_view.select_next_ship(&[ship]);
+        assert_eq!(tui_state.galaxy_view.selected_ship, Some(ship));
+
+        let rendered = render(&mut app, &mut tui_state, 80, 20);
+        assert!(rendered.contains(SHIP_MARKER));
+        assert!(rendered.contains("Ship: Scout"));
+        assert!(rendered.contains("Heading to: Sol (2 cells)"));
+        assert!(rendered.contains("Fuel: 100/100, 16 cells"));
+
+        // a destroyed ship is no longer selected
+        app.world.despawn(ship);
+        render(&mut app, &mut tui_state, 80, 20);
+        assert_eq!(tui_state.galaxy_view.selected_ship, None);
+    }
+
+    #[test]
+    fn test_draw_pipeline() {
+        use bevy::prelude::BuildWorldChildren;
+
+        let (mut app, star) = app_with_system();
+        app.init_resource::<resources::Config>();
+        let planet = query_system_bodies(&app, star)[0].entity;
+        let colony = app
+            .world
+            .spawn((
+                cmp::Location {
+                    z: 1,
+                    ..cmp::Location::at(4, 2)
+                },
+                cmp::Name("Vega I".to_string()),
+            ))
+            .id();
+        let player = app.world.spawn_empty().id();
+        app.world.entity_mut(planet).with_children(|planet| {
+            planet.spawn((
+                cmp::Structure::Pipeline,
+                cmp::Owner(player),
+                cmp::structure::PipelineLink {
+                    to: colony,
+                    capacity: 10,
+                    flow: 7,
+                    disrupted: false,
+                },
+            ));
+        });
+        let mut tui_state = TuiState::new(&mut app);
+        assert!(tui_state.galaxy_view.select_cell((1, 1)));
+
+        let rendered = render(&mut app, &mut tui_state, 120, 30);
+        assert!(rendered.contains("Pipelines:"));
+        assert!(rendered.contains("Sol I > Vega I 7/10 per tick"));
+    }
+
+    #[test]
+    fn test_owner_unknown_out_of_sight() {
+        let (mut app, star) = app_with_system();
+        let player = app.world.spawn_empty().id();
+        let rival = app
+            .world
+            .spawn(cmp::Player {
+                name: "Player 2".to_string(),
+                color: cmp::PlayerColor::Green,
+                homeworld: None,
+            })
+            .id();
+        app.world.entity_mut(star).insert(cmp::Owner(rival));
+        let mut fog_of_war = fog::FogOfWar::default();
+        let player_fog = fog_of_war.players.entry(player).or_default();
+        player_fog.explored.insert((1, 1));
+        app.insert_resource(resources::Config {
+            fog_of_war: true,
+            ..Default::default()
+        })
+        .insert_resource(resources::LocalPlayer(player))
+        .insert_resource(fog_of_war);
+        let mut tui_state = TuiState::new(&mut app);
+        assert!(tui_state.galaxy_view.select_cell((1, 1)));
+
+        let rendered = render(&mut app, &mut tui_state, 100, 30);
+        assert!(rendered.contains("Owner: unknown"));
+        assert!(!rendered.contains("Player 2"));
+    }
+
+    #[test]
+    fn test_combat_report_modal() {
+        let (mut app, _) = app_with_system();
+        app.init_resource::<resources::Config>();
+        let ship_report = |name: &str, outcome| combat::ShipReport {
+            name: name.to_string(),
+            owner: Entity::from_raw(0),
+            owner_name: "Player 1".to_string(),
+            hull: cmp::ship::Hull {
+                current: 0,
+                max: 20,
+            },
+            outcome,
+        };
+        app.insert_resource(combat::CombatLog {
+            reports: vec![combat::CombatReport {
+                cell: (1, 1),
+                tick: 12,
+                turn: 0,
+                rounds: 3,
+                ships: vec![
+                    ship_report("Scout", combat::Outcome::Destroyed),
+                    ship_report("Raider", combat::Outcome::Survived),
+                ],
+            }],
+        });
+        let mut tui_state = TuiState::new(&mut app);
+        tui_state.active_modal = Modal::CombatReport;
+
+        let rendered = render(&mut app, &mut tui_state, 100, 30);
+        assert!(rendered.contains("Combat report 1/1"));
+        assert!(rendered.contains("Battle at 1, 1 on tick 12, 3 rounds"));
+        assert!(rendered.contains("Scout (Player 1) destroyed, hull 0/20"));
+        assert!(rendered.contains("r: 1 combat reports"));
+    }
+
+    #[test]
+    fn test_search_counts_matches_past_the_list() {
+        let (mut app, _) = app_with_system();
+        app.init_resource::<resources::Config>();
+        for x in 0..search::MAX_RESULTS as u32 {
+            app.world
+                .spawn((cmp::Name(format!("Vega {}", x)), cmp::Location::at(x, 5)));
+        }
+        let mut tui_state = TuiState::new(&mut app);
+        tui_state.open_search(&mut app);
+        assert_eq!(tui_state.search.results.len(), search::MAX_RESULTS);
+        let num_matches = tui_state.search.num_matches;
+        assert!(num_matches > search::MAX_RESULTS);
+
+        let rendered = render(&mut app, &mut tui_state, 100, 30);
+        assert!(rendered.contains(&format!(
+            "{} matches, showing the best {}",
+            num_matches,
+            search::MAX_RESULTS
+        )));
+    }
+
+    #[test]
+    fn test_draw_system_view() {
+        let (mut app, star) = app_with_system();
+        let planet = query_system_bodies(&app, star)[0].entity;
+        app.world.entity_mut(planet).insert((
+            cmp::Stockpile([(cmp::Cargo::Resources, 20)].into_iter().collect()),
+            cmp::structure::ConstructionQueue(
+                [cmp::structure::Order {
+                    structure: cmp::Structure::Radar,
+                    remaining: 4,
+                }]
+                .into(),
+            ),
+        ));
+        let mut tui_state = TuiState::new(&mut app);
+        tui_state.system_view.star = Some(star);
+        tui_state.active_view = View::System;
+
+        let rendered = render(&mut app, &mut tui_state, 60, 20);
+        assert!(rendered.contains("System: Sol"));
+        assert!(rendered.contains("Sol I"));
+        assert!(rendered.contains("Stockpile:"));
+        assert!(rendered.contains("20 resources"));
+        assert!(rendered.contains("radar (4 ticks)"));
+    }
 }